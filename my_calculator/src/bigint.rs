@@ -0,0 +1,334 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Mul, Neg, Sub};
+
+/// Limbs are stored in base 10^9 so printing needs no conversion.
+const BASE: u64 = 1_000_000_000;
+
+/// An arbitrary-precision integer for exact factorials and integer powers.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct BigInt {
+    negative: bool,
+    /// Little-endian base 10^9 limbs without leading zeros; zero is empty.
+    limbs: Vec<u32>,
+}
+
+impl BigInt {
+    pub fn zero() -> Self {
+        Self {
+            negative: false,
+            limbs: Vec::new(),
+        }
+    }
+
+    pub fn from_i128(value: i128) -> Self {
+        let mut magnitude = value.unsigned_abs();
+        let mut limbs = Vec::new();
+        while magnitude > 0 {
+            limbs.push((magnitude % BASE as u128) as u32);
+            magnitude /= BASE as u128;
+        }
+        Self {
+            negative: value < 0,
+            limbs,
+        }
+    }
+
+    /// Converts a float holding a whole number, or `None` otherwise.
+    pub fn from_f64(value: f64) -> Option<Self> {
+        if !value.is_finite() || value.fract() != 0.0 {
+            return None;
+        }
+        let digits = format!("{:.0}", value.abs());
+        let mut limbs: Vec<u32> = digits
+            .as_bytes()
+            .rchunks(9)
+            .map(|chunk| std::str::from_utf8(chunk).ok()?.parse().ok())
+            .collect::<Option<_>>()?;
+        while limbs.last() == Some(&0) {
+            limbs.pop();
+        }
+        Some(Self {
+            negative: value < 0.0 && !limbs.is_empty(),
+            limbs,
+        })
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        let magnitude = self
+            .limbs
+            .iter()
+            .rev()
+            .fold(0.0, |acc, &limb| acc * BASE as f64 + limb as f64);
+        if self.negative {
+            -magnitude
+        } else {
+            magnitude
+        }
+    }
+
+    pub fn to_u64(&self) -> Option<u64> {
+        if self.negative {
+            return None;
+        }
+        self.limbs.iter().rev().try_fold(0u64, |acc, &limb| {
+            acc.checked_mul(BASE)?.checked_add(limb as u64)
+        })
+    }
+
+    /// The value modulo 2^128 in two's complement, so exact when it fits in
+    /// an `i128` and correct in the low bits otherwise.
+    pub fn wrapping_to_i128(&self) -> i128 {
+        let magnitude = self.limbs.iter().rev().fold(0i128, |acc, &limb| {
+            acc.wrapping_mul(BASE as i128).wrapping_add(limb as i128)
+        });
+        if self.negative {
+            magnitude.wrapping_neg()
+        } else {
+            magnitude
+        }
+    }
+
+    /// Number of decimal digits, not counting the sign.
+    pub fn digits(&self) -> usize {
+        match self.limbs.last() {
+            Some(top) => (self.limbs.len() - 1) * 9 + top.to_string().len(),
+            None => 1,
+        }
+    }
+
+    pub fn pow(&self, exponent: u32) -> Self {
+        let mut result = Self::from_i128(1);
+        let mut base = self.clone();
+        let mut exponent = exponent;
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = &result * &base;
+            }
+            base = &base * &base;
+            exponent >>= 1;
+        }
+        result
+    }
+
+    pub fn factorial(n: u64) -> Self {
+        let mut result = Self::from_i128(1);
+        for factor in 2..=n {
+            result.multiply_small(factor);
+        }
+        result
+    }
+
+    /// n!! = n·(n-2)·(n-4)·…, down to 2 or 1.
+    pub fn double_factorial(n: u64) -> Self {
+        let mut result = Self::from_i128(1);
+        for factor in (2..=n).rev().step_by(2) {
+            result.multiply_small(factor);
+        }
+        result
+    }
+
+    /// The number of derangements of n items, from !n = n·!(n-1) + (-1)^n.
+    pub fn subfactorial(n: u64) -> Self {
+        let mut result = Self::from_i128(1);
+        for k in 1..=n {
+            result.multiply_small(k);
+            let sign = if k.is_multiple_of(2) { 1 } else { -1 };
+            result = &result + &Self::from_i128(sign);
+        }
+        result
+    }
+
+    fn multiply_small(&mut self, factor: u64) {
+        let mut carry = 0u128;
+        for limb in self.limbs.iter_mut() {
+            let product = *limb as u128 * factor as u128 + carry;
+            *limb = (product % BASE as u128) as u32;
+            carry = product / BASE as u128;
+        }
+        while carry > 0 {
+            self.limbs.push((carry % BASE as u128) as u32);
+            carry /= BASE as u128;
+        }
+    }
+
+    fn compare_magnitudes(a: &[u32], b: &[u32]) -> Ordering {
+        a.len()
+            .cmp(&b.len())
+            .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+    }
+
+    fn add_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+        let mut limbs = Vec::with_capacity(a.len().max(b.len()) + 1);
+        let mut carry = 0u64;
+        for i in 0..a.len().max(b.len()) {
+            let sum = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+            limbs.push((sum % BASE) as u32);
+            carry = sum / BASE;
+        }
+        if carry > 0 {
+            limbs.push(carry as u32);
+        }
+        limbs
+    }
+
+    /// `a - b` for magnitudes with `a >= b`.
+    fn sub_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+        let mut limbs = Vec::with_capacity(a.len());
+        let mut borrow = 0i64;
+        for (i, &limb) in a.iter().enumerate() {
+            let mut difference = limb as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+            borrow = (difference < 0) as i64;
+            if difference < 0 {
+                difference += BASE as i64;
+            }
+            limbs.push(difference as u32);
+        }
+        while limbs.last() == Some(&0) {
+            limbs.pop();
+        }
+        limbs
+    }
+
+    fn with_sign(negative: bool, limbs: Vec<u32>) -> Self {
+        Self {
+            negative: negative && !limbs.is_empty(),
+            limbs,
+        }
+    }
+}
+
+impl Add for &BigInt {
+    type Output = BigInt;
+
+    fn add(self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::with_sign(
+                self.negative,
+                BigInt::add_magnitudes(&self.limbs, &other.limbs),
+            );
+        }
+        match BigInt::compare_magnitudes(&self.limbs, &other.limbs) {
+            Ordering::Less => BigInt::with_sign(
+                other.negative,
+                BigInt::sub_magnitudes(&other.limbs, &self.limbs),
+            ),
+            _ => BigInt::with_sign(
+                self.negative,
+                BigInt::sub_magnitudes(&self.limbs, &other.limbs),
+            ),
+        }
+    }
+}
+
+impl Sub for &BigInt {
+    type Output = BigInt;
+
+    fn sub(self, other: &BigInt) -> BigInt {
+        self + &-other
+    }
+}
+
+impl Mul for &BigInt {
+    type Output = BigInt;
+
+    fn mul(self, other: &BigInt) -> BigInt {
+        if self.limbs.is_empty() || other.limbs.is_empty() {
+            return BigInt::zero();
+        }
+        let mut product = vec![0u64; self.limbs.len() + other.limbs.len()];
+        for (i, &a) in self.limbs.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, &b) in other.limbs.iter().enumerate() {
+                let current = product[i + j] + a as u64 * b as u64 + carry;
+                product[i + j] = current % BASE;
+                carry = current / BASE;
+            }
+            product[i + other.limbs.len()] += carry;
+        }
+        let mut limbs: Vec<u32> = product.into_iter().map(|limb| limb as u32).collect();
+        while limbs.last() == Some(&0) {
+            limbs.pop();
+        }
+        BigInt::with_sign(self.negative != other.negative, limbs)
+    }
+}
+
+impl Neg for &BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::with_sign(!self.negative, self.limbs.clone())
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Some((top, rest)) = self.limbs.split_last() else {
+            return write!(f, "0");
+        };
+        if self.negative {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", top)?;
+        for limb in rest.iter().rev() {
+            write!(f, "{:09}", limb)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_factorial() {
+        assert_eq!(
+            BigInt::factorial(25).to_string(),
+            "15511210043330985984000000"
+        );
+        assert_eq!(BigInt::factorial(0).to_string(), "1");
+        assert_eq!(BigInt::factorial(171).digits(), 310);
+        assert_eq!(BigInt::double_factorial(7).to_string(), "105");
+        assert_eq!(BigInt::double_factorial(8).to_string(), "384");
+        assert_eq!(BigInt::subfactorial(4).to_string(), "9");
+        assert_eq!(BigInt::subfactorial(0).to_string(), "1");
+    }
+
+    #[test]
+    fn test_signed_arithmetic() {
+        let a = BigInt::from_i128(1_000_000_000_000);
+        let b = BigInt::from_i128(-1);
+        assert_eq!((&a + &b).to_string(), "999999999999");
+        assert_eq!((&b - &a).to_string(), "-1000000000001");
+        assert_eq!((&a * &b).to_string(), "-1000000000000");
+        assert_eq!((&a - &a).to_string(), "0");
+    }
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(
+            BigInt::from_i128(2).pow(100).to_string(),
+            "1267650600228229401496703205376"
+        );
+        assert_eq!(
+            BigInt::from_f64(-1e20).unwrap().to_string(),
+            "-100000000000000000000"
+        );
+        assert_eq!(BigInt::from_f64(2.5), None);
+        assert_eq!(BigInt::from_i128(12345).to_u64(), Some(12345));
+        assert_eq!(
+            BigInt::from_i128(2).pow(64).to_f64(),
+            18446744073709551616.0
+        );
+        assert_eq!(BigInt::from_i128(-12345).wrapping_to_i128(), -12345);
+        // 2^130 + 5 keeps only its low 128 bits.
+        let big = &BigInt::from_i128(2).pow(130) + &BigInt::from_i128(5);
+        assert_eq!(big.wrapping_to_i128(), 5);
+    }
+}
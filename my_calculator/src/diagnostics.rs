@@ -0,0 +1,38 @@
+use crate::lexer::Span;
+
+/// Renders `message` followed by the input line with a `^~~~` underline
+/// below the characters covered by `span`.
+pub fn render(source: &str, span: Span, message: &str) -> String {
+    let start = span.start.min(source.len());
+    let end = span.end.clamp(start, source.len());
+    let column = source[..start].chars().count();
+    let width = source[start..end].chars().count().max(1);
+
+    format!(
+        "Error: {}\n  {}\n  {}^{}",
+        message,
+        source,
+        " ".repeat(column),
+        "~".repeat(width - 1)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn underlines_the_span() {
+        let rendered = render("2 + (3 * 4", Span::new(4, 10), "Expected right parenthesis");
+        assert_eq!(
+            rendered,
+            "Error: Expected right parenthesis\n  2 + (3 * 4\n      ^~~~~~"
+        );
+    }
+
+    #[test]
+    fn points_past_the_end_of_input() {
+        let rendered = render("2 +", Span::new(3, 3), "Unexpected end of input");
+        assert_eq!(rendered, "Error: Unexpected end of input\n  2 +\n     ^");
+    }
+}
@@ -0,0 +1,131 @@
+use crate::functions::{Arity, FunctionRegistry};
+use crate::parser::ASTNode;
+use std::collections::HashMap;
+
+/// A function defined in the session, e.g. `f(x, r) = x * (1 + r)^2`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct UserFunction {
+    pub parameters: Vec<String>,
+    pub body: ASTNode,
+}
+
+/// Variables, functions and earlier results, kept for a whole session.
+#[derive(Debug, Default, Clone)]
+pub struct Environment {
+    variables: HashMap<String, ASTNode>,
+    builtins: FunctionRegistry,
+    functions: HashMap<String, UserFunction>,
+    /// Results of earlier evaluations, the most recent last.
+    history: Vec<ASTNode>,
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Looks up a variable. `ans` and `ans1` are the last result, `ans2` the
+    /// one before it, and so on.
+    pub fn get(&self, name: &str) -> Option<&ASTNode> {
+        match Self::results_back(name) {
+            Some(back) => self
+                .history
+                .len()
+                .checked_sub(back)
+                .and_then(|index| self.history.get(index)),
+            None => self.variables.get(name),
+        }
+    }
+
+    /// Whether `name` refers to an earlier result and so cannot be assigned.
+    pub fn is_history(name: &str) -> bool {
+        Self::results_back(name).is_some()
+    }
+
+    pub fn assign(&mut self, name: &str, value: ASTNode) {
+        self.variables.insert(name.to_string(), value);
+    }
+
+    pub fn define(&mut self, name: &str, function: UserFunction) {
+        self.functions.insert(name.to_string(), function);
+    }
+
+    pub fn function(&self, name: &str) -> Option<&UserFunction> {
+        self.functions.get(name)
+    }
+
+    pub fn builtins(&self) -> &FunctionRegistry {
+        &self.builtins
+    }
+
+    /// The built-in functions, to register more.
+    pub fn builtins_mut(&mut self) -> &mut FunctionRegistry {
+        &mut self.builtins
+    }
+
+    /// The arity of every built-in and defined function, for the parser.
+    pub fn function_arities(&self) -> HashMap<String, Arity> {
+        let mut arities = self.builtins.arities();
+        for (name, function) in &self.functions {
+            arities.insert(name.clone(), Arity::Exactly(function.parameters.len()));
+        }
+        arities
+    }
+
+    pub fn push_result(&mut self, value: ASTNode) {
+        self.history.push(value);
+    }
+
+    /// How many results back `ans`, `ans1`, `ans2`, … refer to.
+    fn results_back(name: &str) -> Option<usize> {
+        let suffix = name.strip_prefix("ans")?;
+        if suffix.is_empty() {
+            return Some(1);
+        }
+        suffix.parse::<usize>().ok().filter(|&back| back >= 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history_references() {
+        let mut environment = Environment::new();
+        assert_eq!(environment.get("ans"), None);
+
+        environment.push_result(ASTNode::Number(1.0));
+        environment.push_result(ASTNode::Number(2.0));
+        assert_eq!(environment.get("ans"), Some(&ASTNode::Number(2.0)));
+        assert_eq!(environment.get("ans1"), Some(&ASTNode::Number(2.0)));
+        assert_eq!(environment.get("ans2"), Some(&ASTNode::Number(1.0)));
+        assert_eq!(environment.get("ans3"), None);
+        assert!(Environment::is_history("ans2"));
+        assert!(!Environment::is_history("answer"));
+    }
+
+    #[test]
+    fn test_variables() {
+        let mut environment = Environment::new();
+        environment.assign("x", ASTNode::Number(5.0));
+        assert_eq!(environment.get("x"), Some(&ASTNode::Number(5.0)));
+        assert_eq!(environment.get("y"), None);
+    }
+
+    #[test]
+    fn test_functions() {
+        let mut environment = Environment::new();
+        let function = UserFunction {
+            parameters: vec!["x".to_string(), "r".to_string()],
+            body: ASTNode::Number(1.0),
+        };
+        environment.define("f", function.clone());
+        assert_eq!(environment.function("f"), Some(&function));
+        assert_eq!(
+            environment.function_arities().get("f"),
+            Some(&Arity::Exactly(2))
+        );
+        assert!(environment.function_arities().contains_key("sin"));
+    }
+}
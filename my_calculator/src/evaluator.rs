@@ -1,391 +1,2029 @@
-use crate::lexer::Token;
-use crate::parser::ASTNode;
+use crate::bigint::BigInt;
+use crate::environment::Environment;
+use crate::functions::{call_notation, gamma, Angles, Arity, Builtin};
+use crate::lexer::{Span, Token};
+use crate::parser::{ASTNode, Associativity, Parser};
+use crate::rational::Rational;
+use crate::settings::{AngleMode, Output, Reduction, Settings, Word};
+use crate::step::{Operation, Rule, Step, Trace};
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::f64::consts::{E, PI};
+use std::fmt;
 
-#[derive(Clone, Debug)]
+/// Largest magnitude below which every integer is exactly representable.
+const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_992.0;
+
+/// How often a user-defined function may be expanded in one evaluation.
+/// There is no conditional to end a recursion, so more means it calls
+/// itself forever.
+const MAX_CALLS: usize = 100;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum EvalErrorKind {
+    DivisionByZero,
+    /// `function` is not defined for `value`, e.g. `sqrt` of a negative
+    /// number or `tg` of 90°.
+    DomainError {
+        function: &'static str,
+        value: f64,
+    },
+    /// `function` is only defined for whole numbers, e.g. `2.5!`.
+    NotAnInteger {
+        function: &'static str,
+        value: f64,
+    },
+    /// An exact result would have about `digits` digits, more than `limit`.
+    TooManyDigits {
+        digits: usize,
+        limit: usize,
+    },
+    /// A whole number too large to compute with at all.
+    Overflow,
+    /// The operation produced infinity or NaN, e.g. `10 ^ 400`.
+    NotFinite {
+        operation: Rule,
+    },
+    /// A variable that was never assigned, or an `ans` with no result
+    /// that far back.
+    UndefinedVariable {
+        name: String,
+    },
+    /// A call of a function that was never defined.
+    UndefinedFunction {
+        name: String,
+    },
+    /// A call with the wrong number of arguments.
+    WrongArity {
+        name: String,
+        expected: Arity,
+        got: usize,
+    },
+    /// A user-defined function was expanded more than `limit` times, which
+    /// only happens when it keeps calling itself.
+    RecursionLimit {
+        name: String,
+        limit: usize,
+    },
+    /// A bitwise operator outside the programmer mode.
+    ProgrammerOnly {
+        operator: &'static str,
+    },
+    /// A shift by a negative amount or by the width of the type or more.
+    ShiftOutOfRange {
+        amount: i128,
+        bits: u32,
+    },
+    /// A boolean where `operation` needs a number, e.g. `true + 1`.
+    NotANumber {
+        operation: String,
+        value: bool,
+    },
+    /// A number where `operation` needs a boolean, e.g. `not 1`.
+    NotABoolean {
+        operation: &'static str,
+        value: f64,
+    },
+    /// A literal outside the range of the integer type of the programmer
+    /// mode, e.g. 300 for a `u8`.
+    OutOfRange {
+        value: String,
+        word: Word,
+    },
+    UnknownOperation,
+    /// The expression stopped reducing before it became a number.
+    Unreduced,
+}
+
+impl fmt::Display for EvalErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvalErrorKind::DivisionByZero => write!(f, "division by zero"),
+            EvalErrorKind::DomainError { function, value } => {
+                write!(f, "{} is undefined for {}", function, value)
+            }
+            EvalErrorKind::NotAnInteger { function, value } => {
+                write!(f, "{} needs a whole number, got {}", function, value)
+            }
+            EvalErrorKind::TooManyDigits { digits, limit } => write!(
+                f,
+                "the result would have about {} digits, more than the limit of {} \
+                 (see \"set maxdigits\")",
+                digits, limit
+            ),
+            EvalErrorKind::Overflow => write!(f, "number too large to compute"),
+            EvalErrorKind::NotFinite { operation } => {
+                write!(f, "{} has no finite result", operation)
+            }
+            EvalErrorKind::UndefinedVariable { name } => write!(f, "`{}` is not defined", name),
+            EvalErrorKind::UndefinedFunction { name } => {
+                write!(f, "the function `{}` is not defined", name)
+            }
+            EvalErrorKind::WrongArity {
+                name,
+                expected,
+                got,
+            } => write!(f, "`{}` takes {}, got {}", name, expected, got),
+            EvalErrorKind::RecursionLimit { name, limit } => {
+                write!(f, "`{}` calls itself more than {} times", name, limit)
+            }
+            EvalErrorKind::ProgrammerOnly { operator } => write!(
+                f,
+                "`{}` only works in programmer mode (see \"set programmer\")",
+                operator
+            ),
+            EvalErrorKind::ShiftOutOfRange { amount, bits } => write!(
+                f,
+                "cannot shift by {}, a {}-bit value shifts by 0 to {}",
+                amount,
+                bits,
+                bits - 1
+            ),
+            EvalErrorKind::NotANumber { operation, value } => {
+                write!(f, "{} needs a number, got {}", operation, value)
+            }
+            EvalErrorKind::NotABoolean { operation, value } => {
+                write!(f, "`{}` needs true or false, got {}", operation, value)
+            }
+            EvalErrorKind::OutOfRange { value, word } => {
+                write!(f, "{} does not fit in {}", value, word)
+            }
+            EvalErrorKind::UnknownOperation => write!(f, "unknown operation"),
+            EvalErrorKind::Unreduced => write!(f, "the expression did not reduce to a number"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct EvalError {
+    pub kind: EvalErrorKind,
+    /// Span of the subexpression whose evaluation failed.
+    pub span: Span,
+    /// The failing subexpression as it was written, e.g. `5 / (2 - 2)`,
+    /// or as the failing step showed it if it was not written as such.
+    pub expression: String,
+    /// 1-based number of the step that failed.
+    pub step: usize,
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "at step {}, {} in `{}`",
+            self.step, self.kind, self.expression
+        )
+    }
+}
+
+/// Operations and warnings collected while the expression is reduced into
+/// the next printed step.
+#[derive(Default)]
+struct Changes {
+    operations: Vec<Operation>,
+    warnings: Vec<String>,
+    /// How often each user-defined function was expanded so far, over the
+    /// whole evaluation.
+    calls: HashMap<String, usize>,
+}
+
+impl Changes {
+    fn record(&mut self, rule: Rule, description: String, path: &[usize]) {
+        self.operations.push(Operation {
+            rule,
+            description,
+            path: path.to_vec(),
+        });
+    }
+}
+
+#[derive(Clone, Debug, Default)]
 pub struct Evaluator {
-    evaluation_steps: Vec<String>,
+    settings: Settings,
+    environment: Environment,
 }
 
 impl Evaluator {
     pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn with_settings(settings: Settings) -> Self {
         Self {
-            evaluation_steps: Vec::new(),
+            settings,
+            environment: Environment::default(),
         }
     }
-    pub fn get_evaluation_steps(self) -> Vec<String> {
-        self.evaluation_steps.clone()
+
+    /// An evaluator that substitutes the variables and earlier results of
+    /// `environment`.
+    pub fn with_environment(settings: Settings, environment: Environment) -> Self {
+        Self {
+            settings,
+            environment,
+        }
     }
-    pub fn evaluate_and_print(&mut self, mut ast: ASTNode) -> Result<f64, String> {
-        let mut previous_step: Option<String> = None;
+
+    /// Reduces `ast` to a single number or boolean, recording every step. Nothing is
+    /// printed; hand the trace to a `TraceSink` to show or save it.
+    pub fn evaluate(&self, mut ast: ASTNode) -> Result<Trace, EvalError> {
+        let written = ast.clone();
+        let mut changes = Changes::default();
+        if let Some(word) = self.settings.programmer {
+            ast = Self::word_literals(word, ast, Span::default())?;
+        } else if self.settings.exact {
+            ast = Self::exact_literals(ast);
+        }
+        let expression = self.ast_to_string(&ast);
+        let mut before = expression.clone();
+        let mut steps = Vec::new();
         while !Self::is_single_node(&ast) {
-            let expression_string = Self::ast_to_string(&ast);
-            if Some(&expression_string) != previous_step.as_ref() {
-                println!("= {}", expression_string.clone());
-                previous_step = Some(expression_string.clone());
-                self.evaluation_steps
-                    .push(format!("= {}", expression_string));
+            ast = self
+                .reduce_ast(ast, &[], &mut changes)
+                .map_err(|error| EvalError {
+                    expression: match Self::node_at(&written, error.span) {
+                        Some(node) => self.ast_to_string(node),
+                        None => error.expression,
+                    },
+                    step: steps.len() + 1,
+                    ..error
+                })?;
+            let after = self.ast_to_string(&ast);
+            // Rewrites that do not change the text, such as an angle already
+            // in the current unit, are shown together with the next step.
+            if after == before {
+                continue;
             }
-
-            ast = Self::reduce_ast(ast)?;
+            steps.push(Step {
+                before: std::mem::replace(&mut before, after.clone()),
+                after,
+                operations: std::mem::take(&mut changes.operations),
+                warnings: std::mem::take(&mut changes.warnings),
+            });
         }
 
-        if let ASTNode::Number(result) = ast {
-            let truncated = Self::truncate_number(result);
-            println!("= {}", truncated);
-            self.evaluation_steps.push(format!("= {}", truncated));
-            Ok(truncated)
+        let result = match ast {
+            ASTNode::Boolean(value) => Some(if value { 1.0 } else { 0.0 }),
+            _ => Self::value_of(&ast),
+        };
+        if let Some(result) = result {
+            Ok(Trace {
+                expression,
+                steps,
+                result,
+                value: ast,
+            })
         } else {
-            Err("Evaluation did not reduce to a single number!".to_string())
+            Err(EvalError {
+                kind: EvalErrorKind::Unreduced,
+                span: Span::default(),
+                expression: before,
+                step: steps.len() + 1,
+            })
         }
     }
 
     fn is_single_node(ast: &ASTNode) -> bool {
-        matches!(ast, ASTNode::Number(_))
+        match ast {
+            ASTNode::Number(_) | ASTNode::Integer(_) | ASTNode::Boolean(_) => true,
+            ASTNode::Fraction(fraction) => fraction.is_reduced(),
+            _ => false,
+        }
     }
 
-    fn reduce_ast(ast: ASTNode) -> Result<ASTNode, String> {
+    /// The numeric value of a fully evaluated node.
+    fn value_of(ast: &ASTNode) -> Option<f64> {
         match ast {
-            ASTNode::BinaryOp { left, op, right } => {
-                if let ASTNode::Number(left_val) = *left {
-                    if let ASTNode::Number(right_val) = *right {
-                        let result = Self::evaluate_binary_op(left_val, op, right_val)?;
-                        Ok(ASTNode::Number(result))
-                    } else {
-                        Ok(ASTNode::BinaryOp {
-                            left: Box::new(ASTNode::Number(left_val)),
-                            op,
-                            right: Box::new(Self::reduce_ast(*right)?),
-                        })
-                    }
-                } else {
-                    Ok(ASTNode::BinaryOp {
-                        left: Box::new(Self::reduce_ast(*left)?),
-                        op,
-                        right,
-                    })
-                }
+            ASTNode::Number(value) => Some(*value),
+            ASTNode::Fraction(fraction) => Some(fraction.to_f64()),
+            ASTNode::Integer(integer) => Some(integer.to_f64()),
+            _ => None,
+        }
+    }
+
+    /// Whether a node is fully evaluated, to a number or a boolean.
+    fn is_value(ast: &ASTNode) -> bool {
+        matches!(ast, ASTNode::Boolean(_)) || Self::value_of(ast).is_some()
+    }
+
+    /// The value of an operand, which `reduce_ast` always reduces before
+    /// applying the operation.
+    fn operand_value(ast: &ASTNode) -> f64 {
+        Self::value_of(ast).expect("operands are reduced before their operation")
+    }
+
+    /// The exact value of a fully evaluated node holding a whole number.
+    fn integer_of(ast: &ASTNode) -> Option<BigInt> {
+        match ast {
+            ASTNode::Number(value) if value.abs() < MAX_SAFE_INTEGER => BigInt::from_f64(*value),
+            ASTNode::Fraction(fraction) if fraction.is_integer() => {
+                Some(BigInt::from_i128(fraction.numerator()))
             }
-            ASTNode::UnaryOp { op, operand } => {
-                if let ASTNode::Number(operand_val) = *operand {
-                    let result = Self::evaluate_unary_op(op, operand_val)?;
-                    Ok(ASTNode::Number(result))
-                } else {
-                    let reduced_operand = Self::reduce_ast(*operand)?;
-                    Ok(ASTNode::UnaryOp {
-                        op,
-                        operand: Box::new(reduced_operand),
-                    })
-                }
+            ASTNode::Integer(integer) => Some(integer.clone()),
+            _ => None,
+        }
+    }
+
+    /// Wraps an exact integer result, keeping it as a big integer only when
+    /// a float could not hold it exactly.
+    fn integer(&self, value: BigInt, changes: &mut Changes) -> ASTNode {
+        let approximation = value.to_f64();
+        if approximation.abs() < MAX_SAFE_INTEGER {
+            self.number(approximation, changes)
+        } else {
+            ASTNode::Integer(value)
+        }
+    }
+
+    /// Rejects results that would have more digits than the configured limit,
+    /// before spending any time computing them.
+    fn check_digits(&self, digits: f64) -> Result<(), EvalErrorKind> {
+        if digits > self.settings.max_digits as f64 {
+            Err(EvalErrorKind::TooManyDigits {
+                digits: digits.round() as usize,
+                limit: self.settings.max_digits,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Exact factorial, double factorial or subfactorial of `n`, depending
+    /// on `op`.
+    fn factorial(
+        &self,
+        op: &Token,
+        n: &BigInt,
+        changes: &mut Changes,
+    ) -> Result<ASTNode, EvalErrorKind> {
+        if n.is_negative() {
+            return Err(EvalErrorKind::DomainError {
+                function: Self::factorial_name(op),
+                value: n.to_f64(),
+            });
+        }
+        let Some(n) = n.to_u64() else {
+            return Err(EvalErrorKind::Overflow);
+        };
+        let result = match op {
+            Token::DoubleFact => {
+                self.check_digits(Self::log10_double_factorial(n) + 1.0)?;
+                BigInt::double_factorial(n)
             }
-            ASTNode::Function { func, argument } => {
-                if let ASTNode::Number(arg_val) = *argument {
-                    let result = Self::evaluate_function(func, arg_val)?;
-                    Ok(ASTNode::Number(result))
-                } else {
-                    let reduced_argument = Self::reduce_ast(*argument)?;
-                    Ok(ASTNode::Function {
-                        func,
-                        argument: Box::new(reduced_argument),
-                    })
-                }
+            Token::Subfact => {
+                // !n is the integer nearest to n!/e, so it has as many digits.
+                self.check_digits(Self::log10_factorial(n) + 1.0)?;
+                BigInt::subfactorial(n)
+            }
+            _ => {
+                self.check_digits(Self::log10_factorial(n) + 1.0)?;
+                BigInt::factorial(n)
             }
-            ASTNode::LogBase { base, number } => {
-                let reduced_base = Self::reduce_ast(*base)?;
-                let reduced_number = Self::reduce_ast(*number)?;
+        };
+        Ok(self.integer(result, changes))
+    }
 
-                match (reduced_base, reduced_number) {
-                    (ASTNode::Number(base_val), ASTNode::Number(number_val)) => {
-                        let result = Self::evaluate_log_base(base_val, number_val)?;
-                        Ok(ASTNode::Number(result))
-                    }
-                    (reduced_base, reduced_number) => Ok(ASTNode::LogBase {
-                        base: Box::new(reduced_base),
-                        number: Box::new(reduced_number),
-                    }),
+    /// Stirling's approximation of log10(n!).
+    fn log10_factorial(n: u64) -> f64 {
+        let x = n.max(1) as f64;
+        x * (x / E).log10() + 0.5 * (2.0 * PI * x).log10()
+    }
+
+    /// log10(n!!), from (2k)!! = 2^k k! and (2k+1)!! = (2k+1)! / (2^k k!).
+    fn log10_double_factorial(n: u64) -> f64 {
+        let k = n / 2;
+        let even = k as f64 * 2f64.log10() + Self::log10_factorial(k);
+        if n.is_multiple_of(2) {
+            even
+        } else {
+            Self::log10_factorial(n) - even
+        }
+    }
+
+    fn factorial_name(op: &Token) -> &'static str {
+        match op {
+            Token::DoubleFact => "double factorial",
+            Token::Subfact => "subfactorial",
+            _ => "factorial",
+        }
+    }
+
+    /// Writes out what a factorial of a small whole number stands for, e.g.
+    /// `5! = 5·4·3·2·1`, or the gamma function a real factorial uses.
+    fn describe_factorial(&self, op: &Token, operand: &ASTNode, shown: &str) -> String {
+        let small = Self::integer_of(operand)
+            .and_then(|n| n.to_u64())
+            .filter(|&n| n <= 10);
+        let product = |n: u64, step: usize| {
+            let factors: Vec<String> = (1..=n.max(1))
+                .rev()
+                .step_by(step)
+                .map(|factor| factor.to_string())
+                .collect();
+            factors.join("·")
+        };
+        match (op, small) {
+            (Token::Fact, Some(n)) => format!("{} = {}", shown, product(n, 1)),
+            (Token::DoubleFact, Some(n)) => format!("{} = {}", shown, product(n, 2)),
+            (Token::Subfact, Some(n)) => {
+                let mut terms = "1".to_string();
+                for k in 1..=n {
+                    let sign = if k.is_multiple_of(2) { '+' } else { '-' };
+                    terms.push_str(&format!(" {} 1/{}!", sign, k));
                 }
+                format!("{} = {}!·({})", shown, n, terms)
             }
-            ASTNode::Grouping(expression) => {
-                let reduced_expression = Self::reduce_ast(*expression)?;
-                if let ASTNode::Number(_) = reduced_expression {
-                    Ok(reduced_expression)
-                } else {
-                    Ok(ASTNode::Grouping(Box::new(reduced_expression)))
-                }
+            (Token::Fact, None) if self.settings.gamma_factorial => {
+                let argument = Self::operand_value(operand) + 1.0;
+                let argument = self.settings.precision.format(argument);
+                format!("{} = Γ({})", shown, argument)
             }
-            ASTNode::Pi => Ok(ASTNode::Number(Self::truncate_number(PI))),
-            ASTNode::Euler => Ok(ASTNode::Number(Self::truncate_number(E))),
-            _ => Ok(ast),
+            _ => format!("evaluate {}", shown),
         }
     }
 
-    fn evaluate_binary_op(left: f64, op: Token, right: f64) -> Result<f64, String> {
-        match op {
-            Token::Plus => Ok(left + right),
-            Token::Minus => Ok(left - right),
-            Token::Multiply => Ok(left * right),
-            Token::Divide => {
-                if right == 0.0 {
-                    Err("Can't divide number by 0".to_string())
-                } else {
-                    Ok(left / right)
+    /// Exact `+ - *` and non-negative integer powers of whole numbers.
+    /// Returns `None` for other operators so the caller uses floats.
+    fn reduce_integers(
+        &self,
+        left: &BigInt,
+        op: &Token,
+        right: &BigInt,
+        changes: &mut Changes,
+    ) -> Result<Option<ASTNode>, EvalErrorKind> {
+        let result = match op {
+            Token::Plus => left + right,
+            Token::Minus => left - right,
+            Token::Multiply => {
+                let digits = (left.digits() + right.digits()) as f64;
+                self.check_digits(digits)?;
+                left * right
+            }
+            Token::Exponent => {
+                if right.is_negative() {
+                    return Ok(None);
                 }
+                let Some(exponent) = right.to_u64().and_then(|e| u32::try_from(e).ok()) else {
+                    return Err(EvalErrorKind::Overflow);
+                };
+                let digits = exponent as f64 * left.to_f64().abs().log10().max(0.0) + 1.0;
+                self.check_digits(digits)?;
+                left.pow(exponent)
             }
-            Token::Exponent => Ok(left.powf(right)),
-            _ => Err("Unknown binary operator".to_string()),
+            _ => return Ok(None),
+        };
+        Ok(Some(self.integer(result, changes)))
+    }
+
+    /// Rewrites number literals as exact fractions for the exact mode.
+    /// A quotient of two integer literals becomes a single fraction, so
+    /// `1/3 + 1/6` is shown as two fractions rather than two divisions.
+    fn exact_literals(ast: ASTNode) -> ASTNode {
+        let exact = |node: Box<ASTNode>| Box::new(Self::exact_literals(*node));
+        match ast {
+            ASTNode::Number(value) => match Rational::from_f64(value) {
+                Some(fraction) => ASTNode::Fraction(fraction),
+                None => ast,
+            },
+            ASTNode::BinaryOp {
+                left,
+                op,
+                right,
+                span,
+            } => {
+                if let (ASTNode::Number(numerator), &Token::Divide, ASTNode::Number(denominator)) =
+                    (&*left, &op, &*right)
+                {
+                    // A quotient too large for a fraction, such as
+                    // `1e300 / 3`, is left to float arithmetic.
+                    let whole = |value: f64| {
+                        (value.fract() == 0.0 && value.abs() < i128::MAX as f64)
+                            .then_some(value as i128)
+                    };
+                    if let (Some(numerator), Some(denominator)) =
+                        (whole(*numerator), whole(*denominator))
+                    {
+                        if let Some(fraction) = Rational::new(numerator, denominator) {
+                            return ASTNode::Fraction(fraction);
+                        }
+                    }
+                }
+                ASTNode::BinaryOp {
+                    left: exact(left),
+                    op,
+                    right: exact(right),
+                    span,
+                }
+            }
+            ASTNode::UnaryOp { op, operand, span } => ASTNode::UnaryOp {
+                op,
+                operand: exact(operand),
+                span,
+            },
+            ASTNode::Angle { value, unit, span } => ASTNode::Angle {
+                value: exact(value),
+                unit,
+                span,
+            },
+            ASTNode::Grouping(expression) => ASTNode::Grouping(exact(expression)),
+            ASTNode::Call { name, args, span } => ASTNode::Call {
+                name,
+                args: args.into_iter().map(Self::exact_literals).collect(),
+                span,
+            },
+            ASTNode::Pi
+            | ASTNode::Euler
+            | ASTNode::Fraction(_)
+            | ASTNode::Integer(_)
+            | ASTNode::Boolean(_)
+            | ASTNode::Variable { .. } => ast,
         }
     }
 
-    fn evaluate_unary_op(op: Token, operand: f64) -> Result<f64, String> {
-        match op {
-            Token::Minus => Ok(-operand),
-            Token::Fact => {
-                if operand != operand.floor() || operand < 0.0 {
-                    return Err("Factorial is only defined for non-negative integers!".to_string());
+    /// Exact `+ - * /` and integer powers of two fractions. Sums with
+    /// different denominators are first rewritten over the common
+    /// denominator, as their own step. Returns `None` when the result is not
+    /// exact or does not fit, so the caller falls back to floats.
+    fn reduce_fractions(
+        left: Rational,
+        op: &Token,
+        right: Rational,
+        span: Span,
+    ) -> Option<ASTNode> {
+        let result = match op {
+            Token::Plus | Token::Minus => {
+                if left.denominator() != right.denominator() {
+                    let denominator = left.common_denominator(&right)?;
+                    return Some(ASTNode::BinaryOp {
+                        left: Box::new(ASTNode::Fraction(left.expanded_to(denominator)?)),
+                        op: op.clone(),
+                        right: Box::new(ASTNode::Fraction(right.expanded_to(denominator)?)),
+                        span,
+                    });
                 }
-                let n = operand as u64;
-                if n == 0 {
-                    Ok(1.0)
+                if *op == Token::Plus {
+                    left.checked_add(&right)?
                 } else {
-                    Ok((1..=n).map(|x| x as f64).product())
+                    left.checked_sub(&right)?
                 }
             }
-            _ => Err("Unknown unary operator".to_string()),
-        }
+            Token::Multiply => left.checked_mul(&right)?,
+            Token::Divide => left.checked_div(&right)?,
+            Token::IntDivide => Rational::from_integer(left.checked_div(&right)?.floor()),
+            Token::Modulo => {
+                let quotient = Rational::from_integer(left.checked_div(&right)?.floor());
+                let multiple = right.checked_mul(&quotient)?;
+                let denominator = left.common_denominator(&multiple)?;
+                left.expanded_to(denominator)?
+                    .checked_sub(&multiple.expanded_to(denominator)?)?
+            }
+            Token::Exponent if right.is_integer() => {
+                left.checked_pow(i32::try_from(right.numerator()).ok()?)?
+            }
+            _ => return None,
+        };
+        Some(ASTNode::Fraction(result))
     }
-    fn evaluate_log_base(base: f64, number: f64) -> Result<f64, String> {
-        if base <= 0.0 {
-            return Err("The base of logarithm must be greater than zero!".to_string());
-        }
 
-        if (base - 1.0).abs() <= f64::EPSILON {
-            return Err("The base of logarithm cannot be 1!".to_string());
+    /// Wraps the result of an operation, rounding it to the display
+    /// precision first when school rounding is on. In exact mode whole
+    /// results such as `sqrt(4)` stay usable in fraction arithmetic.
+    fn number(&self, value: f64, changes: &mut Changes) -> ASTNode {
+        if self.settings.exact && value.fract() == 0.0 && value.abs() < MAX_SAFE_INTEGER {
+            return ASTNode::Fraction(Rational::from_integer(value as i128));
+        }
+        if self.settings.school_rounding {
+            let rounded = self.settings.precision.round(value);
+            if rounded != value {
+                changes.warnings.push(format!(
+                    "{} was rounded to {}",
+                    value,
+                    self.settings.precision.format(rounded)
+                ));
+            }
+            ASTNode::Number(rounded)
+        } else {
+            ASTNode::Number(value)
         }
+    }
 
-        if number <= 0.0 {
-            return Err("Can't calculate logarithm of negative number!".to_string());
+    /// Reduces the operands that are not numbers yet. In strict mode only
+    /// the leftmost one is reduced, so together with `reduce_ast` descending
+    /// into it every step applies exactly one operation, the leftmost
+    /// innermost one. In level mode all of them are reduced in the same step.
+    fn reduce_operands(
+        &self,
+        operands: &mut [&mut ASTNode],
+        path: &[usize],
+        changes: &mut Changes,
+    ) -> Result<(), EvalError> {
+        for (index, operand) in operands.iter_mut().enumerate() {
+            if !Self::is_value(operand) {
+                let node = std::mem::replace(&mut **operand, ASTNode::Number(0.0));
+                **operand = self.reduce_ast(node, &Self::child(path, index), changes)?;
+                if self.settings.reduction == Reduction::Strict {
+                    break;
+                }
+            }
         }
+        Ok(())
+    }
 
-        Ok(number.ln() / base.ln())
+    fn child(path: &[usize], index: usize) -> Vec<usize> {
+        let mut child = path.to_vec();
+        child.push(index);
+        child
     }
 
-    fn evaluate_function(func: Token, arg: f64) -> Result<f64, String> {
-        match func {
-            Token::Abs => Ok(arg.abs()),
-            Token::Sqrt => {
-                if arg < 0.0 {
-                    Err("Can't calculate square root of negative number!".to_string())
-                } else {
-                    Ok(arg.sqrt())
+    /// Descends to the operations whose operands are all numbers and applies
+    /// them, recording what was done in `changes`. `path` locates `ast` in
+    /// the whole expression.
+    fn reduce_ast(
+        &self,
+        ast: ASTNode,
+        path: &[usize],
+        changes: &mut Changes,
+    ) -> Result<ASTNode, EvalError> {
+        match ast {
+            ASTNode::BinaryOp {
+                left,
+                op: op @ (Token::Plus | Token::Minus),
+                right,
+                span,
+            } if matches!(
+                *right,
+                ASTNode::UnaryOp {
+                    op: Token::Percent,
+                    ..
                 }
+            ) =>
+            {
+                self.reduce_percent_of(*left, op, *right, span, path, changes)
             }
-            Token::Ln => {
-                if arg <= 0.0 {
-                    Err("Can't calculate logarithm of negative number!".to_string())
+            ASTNode::BinaryOp {
+                left,
+                op,
+                right,
+                span,
+            } if matches!(
+                (&*left, &op),
+                (ASTNode::Boolean(false), Token::And) | (ASTNode::Boolean(true), Token::Or)
+            ) && !Self::is_value(&right) =>
+            {
+                // `false and x` is false whatever x is, so x is not evaluated.
+                let result = ASTNode::Boolean(op == Token::Or);
+                let description = format!(
+                    "{} is {} without evaluating the right side",
+                    self.ast_to_string(&ASTNode::BinaryOp {
+                        left,
+                        op,
+                        right,
+                        span,
+                    }),
+                    self.ast_to_string(&result)
+                );
+                changes.record(Rule::Logic, description, path);
+                Ok(result)
+            }
+            ASTNode::BinaryOp {
+                mut left,
+                op,
+                mut right,
+                span,
+            } if !Self::is_value(&left) || !Self::is_value(&right) => {
+                self.reduce_operands(&mut [&mut *left, &mut *right], path, changes)?;
+                Ok(ASTNode::BinaryOp {
+                    left,
+                    op,
+                    right,
+                    span,
+                })
+            }
+            ASTNode::UnaryOp { op, operand, span } if !Self::is_value(&operand) => {
+                Ok(ASTNode::UnaryOp {
+                    op,
+                    operand: Box::new(self.reduce_ast(*operand, &Self::child(path, 0), changes)?),
+                    span,
+                })
+            }
+            ASTNode::Angle { value, unit, span } if !Self::is_value(&value) => Ok(ASTNode::Angle {
+                value: Box::new(self.reduce_ast(*value, &Self::child(path, 0), changes)?),
+                unit,
+                span,
+            }),
+            ASTNode::Call {
+                name,
+                mut args,
+                span,
+            } if !args.iter().all(Self::is_value) => {
+                let mut operands: Vec<&mut ASTNode> = args.iter_mut().collect();
+                self.reduce_operands(&mut operands, path, changes)?;
+                Ok(ASTNode::Call { name, args, span })
+            }
+            ASTNode::Grouping(expression) => {
+                let reduced_expression =
+                    self.reduce_ast(*expression, &Self::child(path, 0), changes)?;
+                if Self::is_value(&reduced_expression) {
+                    Ok(reduced_expression)
                 } else {
-                    Ok(arg.ln())
+                    Ok(ASTNode::Grouping(Box::new(reduced_expression)))
                 }
             }
-            Token::Sin => Ok(Self::truncate_number(arg.to_radians().sin())),
-            Token::Cos => Ok(Self::truncate_number(arg.to_radians().cos())),
-            Token::Tg => {
-                let radians = arg.to_radians();
-
-                if (radians / (PI / 2.0)).rem_euclid(2.0).abs() < 1e-10 {
-                    Err("Can't calculate tg for that number, cosine is 0!".to_string())
-                } else {
-                    Ok(Self::truncate_number(radians.tan()))
+            _ => {
+                if let Err(kind) = self.check_operands(&ast) {
+                    return Err(EvalError {
+                        kind,
+                        span: Self::span_of(&ast),
+                        expression: self.ast_to_string(&ast),
+                        step: 0,
+                    });
+                }
+                // Comparisons and logic work the same in programmer mode.
+                match self.settings.programmer {
+                    Some(word) if !Self::is_logical(&ast) => {
+                        self.apply_word(word, ast, path, changes)
+                    }
+                    _ => self.apply(ast, path, changes),
                 }
             }
-            Token::Cotg => {
-                let radians = arg.to_radians();
+        }
+    }
+
+    /// Reduces `a + b%` and `a - b%`, which add or subtract b percent of a,
+    /// the way a pocket calculator does: `200 + 10%` is `200 + 20`. The
+    /// percent is kept until both a and b are numbers, and then replaced by
+    /// its share of a as a step of its own.
+    fn reduce_percent_of(
+        &self,
+        mut left: ASTNode,
+        op: Token,
+        right: ASTNode,
+        span: Span,
+        path: &[usize],
+        changes: &mut Changes,
+    ) -> Result<ASTNode, EvalError> {
+        let ASTNode::UnaryOp {
+            op: percent,
+            operand,
+            span: percent_span,
+        } = right
+        else {
+            unreachable!("only called with a percent on the right");
+        };
+        let mut operand = *operand;
+        let right_path = Self::child(path, 1);
+        let reduce_left = !Self::is_value(&left);
+        if reduce_left {
+            left = self.reduce_ast(left, &Self::child(path, 0), changes)?;
+        }
+        let reduce_operand = !Self::is_value(&operand)
+            && (!reduce_left || self.settings.reduction == Reduction::Level);
+        if reduce_operand {
+            operand = self.reduce_ast(operand, &Self::child(&right_path, 0), changes)?;
+        }
+        if reduce_left || reduce_operand {
+            return Ok(ASTNode::BinaryOp {
+                left: Box::new(left),
+                op,
+                right: Box::new(ASTNode::UnaryOp {
+                    op: percent,
+                    operand: Box::new(operand),
+                    span: percent_span,
+                }),
+                span,
+            });
+        }
+        if let Some(ASTNode::Boolean(value)) = [&left, &operand]
+            .into_iter()
+            .find(|node| matches!(node, ASTNode::Boolean(_)))
+        {
+            return Err(EvalError {
+                kind: EvalErrorKind::NotANumber {
+                    operation: "`%`".to_string(),
+                    value: *value,
+                },
+                span: percent_span,
+                expression: self.ast_to_string(&operand) + "%",
+                step: 0,
+            });
+        }
+        let exact = match (&left, &operand) {
+            (ASTNode::Fraction(whole), ASTNode::Fraction(rate)) => whole
+                .checked_mul(rate)
+                .and_then(|share| share.checked_div(&Rational::from_integer(100)))
+                .map(|share| ASTNode::Fraction(share.reduced())),
+            _ => None,
+        };
+        let share = match exact {
+            Some(share) => share,
+            None => {
+                let share = Self::operand_value(&left) * Self::operand_value(&operand) / 100.0;
+                let share = Self::finite(share, Rule::Percent).map_err(|kind| EvalError {
+                    kind,
+                    span: percent_span,
+                    expression: self.ast_to_string(&operand) + "%",
+                    step: 0,
+                })?;
+                self.number(share, changes)
+            }
+        };
+        let description = format!(
+            "{}% of {} = {}",
+            self.ast_to_string(&operand),
+            self.ast_to_string(&left),
+            self.ast_to_string(&share)
+        );
+        changes.record(Rule::Percent, description, &right_path);
+        Ok(ASTNode::BinaryOp {
+            left: Box::new(left),
+            op,
+            right: Box::new(share),
+            span,
+        })
+    }
 
-                if (radians / (PI)).rem_euclid(1.0).abs() < 1e-10 {
-                    Err("Can't calculate cotg for that number, it is 0!".to_string())
-                } else {
-                    Ok(Self::truncate_number(1.0 / radians.tan()))
+    /// Applies the operation of a node whose operands are all numbers.
+    fn apply(
+        &self,
+        ast: ASTNode,
+        path: &[usize],
+        changes: &mut Changes,
+    ) -> Result<ASTNode, EvalError> {
+        if Self::is_logical(&ast) {
+            return Ok(self.apply_logic(ast, path, changes));
+        }
+        let shown = self.ast_to_string(&ast);
+        let at = |span: Span| {
+            let expression = shown.clone();
+            move |kind: EvalErrorKind| EvalError {
+                kind,
+                span,
+                expression,
+                // Filled in by `evaluate`, which counts the steps.
+                step: 0,
+            }
+        };
+        match ast {
+            ASTNode::BinaryOp {
+                left,
+                op,
+                right,
+                span,
+            } => {
+                let left_val = Self::operand_value(&left);
+                let right_val = Self::operand_value(&right);
+                let (rule, description) = self.describe_binary(&left, &op, &right);
+                if let (ASTNode::Fraction(left), ASTNode::Fraction(right)) = (&*left, &*right) {
+                    if let Some(node) = Self::reduce_fractions(*left, &op, *right, span) {
+                        if matches!(node, ASTNode::BinaryOp { .. }) {
+                            let description = format!("write {} over a common denominator", shown);
+                            changes.record(Rule::CommonDenominator, description, path);
+                        } else {
+                            changes.record(rule, description, path);
+                        }
+                        return Ok(node);
+                    }
+                }
+                if let (Some(left), Some(right)) =
+                    (Self::integer_of(&left), Self::integer_of(&right))
+                {
+                    if let Some(node) = self
+                        .reduce_integers(&left, &op, &right, changes)
+                        .map_err(at(span))?
+                    {
+                        changes.record(rule, description, path);
+                        return Ok(node);
+                    }
+                }
+                if op == Token::Exponent && left_val < 0.0 {
+                    if let Some(exponent) = Self::odd_root_exponent(&right) {
+                        let magnitude = (-left_val).powf(exponent.to_f64());
+                        let result = if exponent.numerator() % 2 == 0 {
+                            magnitude
+                        } else {
+                            -magnitude
+                        };
+                        let result = Self::finite(result, Rule::RealRoot).map_err(at(span))?;
+                        let description = format!(
+                            "take the real root of {}: the exponent {} has an odd denominator",
+                            self.ast_to_string(&left),
+                            exponent
+                        );
+                        changes.record(Rule::RealRoot, description, path);
+                        return Ok(self.number(result, changes));
+                    }
                 }
+                let result = Self::evaluate_binary_op(left_val, &op, right_val)
+                    .and_then(|result| Self::finite(result, rule))
+                    .map_err(at(span))?;
+                changes.record(rule, description, path);
+                Ok(self.number(result, changes))
             }
-            Token::Sec => {
-                let radians = arg.to_radians();
-                if radians.cos().abs() < 1e-10 {
-                    Err("Can't calculate sec for that number, cosine is 0!".to_string())
-                } else {
-                    Ok(Self::truncate_number(1.0 / radians.cos()))
+            ASTNode::UnaryOp { op, operand, span } => {
+                let (rule, description) = match op {
+                    Token::Minus => (
+                        Rule::Negation,
+                        format!("negate {}", self.ast_to_string(&operand)),
+                    ),
+                    Token::Percent => (
+                        Rule::Percent,
+                        format!("{} = {} / 100", shown, self.ast_to_string(&operand)),
+                    ),
+                    Token::DoubleFact => (
+                        Rule::DoubleFactorial,
+                        self.describe_factorial(&op, &operand, &shown),
+                    ),
+                    Token::Subfact => (
+                        Rule::Subfactorial,
+                        self.describe_factorial(&op, &operand, &shown),
+                    ),
+                    _ => (
+                        Rule::Factorial,
+                        self.describe_factorial(&op, &operand, &shown),
+                    ),
+                };
+                let node = match (&op, &*operand) {
+                    (Token::Minus, ASTNode::Fraction(fraction)) => {
+                        fraction.checked_neg().map(ASTNode::Fraction)
+                    }
+                    (Token::Minus, ASTNode::Integer(integer)) => Some(ASTNode::Integer(-integer)),
+                    (Token::Percent, ASTNode::Fraction(fraction)) => fraction
+                        .checked_div(&Rational::from_integer(100))
+                        .map(ASTNode::Fraction),
+                    (Token::Fact | Token::DoubleFact | Token::Subfact, _) => {
+                        match Self::integer_of(&operand) {
+                            Some(n) => Some(self.factorial(&op, &n, changes).map_err(at(span))?),
+                            None => None,
+                        }
+                    }
+                    _ => None,
+                };
+                let node = match node {
+                    Some(node) => node,
+                    None => {
+                        let operand_val = Self::operand_value(&operand);
+                        let gamma_factorial = self.settings.gamma_factorial;
+                        let result = Self::evaluate_unary_op(&op, operand_val, gamma_factorial)
+                            .and_then(|result| Self::finite(result, rule))
+                            .map_err(at(span))?;
+                        self.number(result, changes)
+                    }
+                };
+                changes.record(rule, description, path);
+                Ok(node)
+            }
+            ASTNode::Angle { value, unit, .. } => {
+                let angle = Self::operand_value(&value);
+                let angle_mode = self.settings.angle_mode;
+                if unit != angle_mode {
+                    let description = format!("convert {} to {}", shown, angle_mode);
+                    changes.record(Rule::AngleConversion, description, path);
                 }
+                Ok(self.number(unit.convert(angle, angle_mode), changes))
+            }
+            ASTNode::Pi | ASTNode::Euler => {
+                let value = if ast == ASTNode::Pi { PI } else { E };
+                let node = self.number(value, changes);
+                let description = format!("replace {} with {}", shown, self.ast_to_string(&node));
+                changes.record(Rule::Constant, description, path);
+                Ok(node)
             }
-            Token::Csc => {
-                let radians = arg.to_radians();
-                if radians.sin().abs() < 1e-10 {
-                    Err("Can't calculate csc for that number, sine is 0!".to_string())
+            ASTNode::Variable { name, span } => {
+                let value = match self.environment.get(&name) {
+                    Some(value) => value.clone(),
+                    None => return Err(at(span)(EvalErrorKind::UndefinedVariable { name })),
+                };
+                let value = if self.settings.exact {
+                    Self::exact_literals(value)
                 } else {
-                    Ok(Self::truncate_number(1.0 / radians.sin()))
-                }
+                    value
+                };
+                let description = format!("substitute {} = {}", name, self.ast_to_string(&value));
+                changes.record(Rule::Substitution, description, path);
+                Ok(value)
             }
-            Token::Asin => {
-                if !(-1.0..=1.0).contains(&arg) {
-                    Err("Can't calculate asin for values outside of [-1, 1]".to_string())
+            ASTNode::Call { name, args, span } => {
+                let error = at(span);
+                if let Some(builtin) = self.environment.builtins().get(&name) {
+                    let result = self.call_builtin(builtin, &args).map_err(error)?;
+                    let description = match builtin.describe {
+                        Some(describe) => {
+                            let args: Vec<String> =
+                                args.iter().map(|arg| self.ast_to_string(arg)).collect();
+                            describe(&args)
+                        }
+                        None => format!("evaluate {}", shown),
+                    };
+                    changes.record(builtin.rule, description, path);
+                    return Ok(self.number(result, changes));
+                }
+                let Some(function) = self.environment.function(&name) else {
+                    return Err(error(EvalErrorKind::UndefinedFunction { name }));
+                };
+                if args.len() != function.parameters.len() {
+                    return Err(error(EvalErrorKind::WrongArity {
+                        expected: Arity::Exactly(function.parameters.len()),
+                        got: args.len(),
+                        name,
+                    }));
+                }
+                let calls = changes.calls.entry(name.clone()).or_default();
+                *calls += 1;
+                if *calls > MAX_CALLS {
+                    return Err(error(EvalErrorKind::RecursionLimit {
+                        name,
+                        limit: MAX_CALLS,
+                    }));
+                }
+                let bindings: Vec<String> = function
+                    .parameters
+                    .iter()
+                    .zip(&args)
+                    .map(|(parameter, arg)| format!("{} = {}", parameter, self.ast_to_string(arg)))
+                    .collect();
+                let description = format!("substitute {} into {}", bindings.join(", "), name);
+                changes.record(Rule::Substitution, description, path);
+                let arguments: HashMap<&str, ASTNode> = function
+                    .parameters
+                    .iter()
+                    .map(String::as_str)
+                    .zip(args)
+                    .collect();
+                let body = Self::substitute(function.body.clone(), &arguments, span);
+                Ok(if self.settings.exact {
+                    Self::exact_literals(body)
                 } else {
-                    Ok(Self::truncate_number(arg.asin()))
+                    body
+                })
+            }
+            ASTNode::Fraction(fraction) => {
+                let reduced = fraction.reduced();
+                let description = format!("reduce {} to {}", shown, reduced);
+                changes.record(Rule::Simplification, description, path);
+                Ok(ASTNode::Fraction(reduced))
+            }
+            _ => Ok(ast),
+        }
+    }
+
+    /// Applies an operation in programmer mode, where every value is a whole
+    /// number of the integer type `word`. Arithmetic and bitwise operators
+    /// wrap around on overflow; anything else is evaluated as usual and
+    /// must give a whole number.
+    fn apply_word(
+        &self,
+        word: Word,
+        ast: ASTNode,
+        path: &[usize],
+        changes: &mut Changes,
+    ) -> Result<ASTNode, EvalError> {
+        let shown = self.ast_to_string(&ast);
+        let at = |span: Span| {
+            let expression = shown.clone();
+            move |kind: EvalErrorKind| EvalError {
+                kind,
+                span,
+                expression,
+                step: 0,
+            }
+        };
+        let (result, overflow, rule, description) = match ast {
+            ASTNode::BinaryOp {
+                left,
+                op,
+                right,
+                span,
+            } => {
+                let a = Self::word_value(word, &left).map_err(at(span))?;
+                let b = Self::word_value(word, &right).map_err(at(span))?;
+                let (result, overflow) = Self::word_binary(word, a, &op, b).map_err(at(span))?;
+                let (rule, mut description) = self.describe_binary(&left, &op, &right);
+                if op == Token::Divide && a % b != 0 {
+                    description.push_str(", dropping the remainder");
                 }
+                (result, overflow, rule, description)
             }
-            Token::Acos => {
-                if !(-1.0..=1.0).contains(&arg) {
-                    Err("Can't calculate acos for values outside of [-1, 1]".to_string())
+            ASTNode::UnaryOp {
+                op: op @ (Token::Minus | Token::BitNot),
+                operand,
+                span,
+            } => {
+                let value = Self::word_value(word, &operand).map_err(at(span))?;
+                let operand = self.ast_to_string(&operand);
+                if op == Token::Minus {
+                    let result = word.wrap(-value);
+                    let description = format!("negate {}", operand);
+                    (result, result != -value, Rule::Negation, description)
                 } else {
-                    Ok(Self::truncate_number(arg.acos()))
+                    let description = format!("invert the bits of {}", operand);
+                    (word.wrap(!value), false, Rule::Bitwise, description)
                 }
             }
-            Token::Atg => Ok(Self::truncate_number(arg.atan())),
-            Token::Actg => {
-                if arg == 0.0 {
-                    Err("Can't calculate actg for 0!".to_string())
-                } else {
-                    Ok(Self::truncate_number((PI / 2.0) - arg.atan()))
+            ast => {
+                let span = Self::span_of(&ast);
+                let recorded = changes.operations.len();
+                let node = self.apply(ast, path, changes)?;
+                // A call of a defined function gives its body, which is
+                // reduced further, and a variable may hold a boolean.
+                if Self::value_of(&node).is_none() {
+                    return Ok(node);
+                }
+                let exact = Self::integer_of(&node).map(|integer| integer.wrapping_to_i128());
+                let value = Self::word_value(word, &node).map_err(at(span))?;
+                if exact != Some(value) {
+                    changes.warnings.push(format!(
+                        "{} overflows {} and wraps to {}",
+                        shown,
+                        word,
+                        word.format(value, self.settings.base)
+                    ));
+                }
+                if let Some(operation) = changes.operations[recorded..].last_mut() {
+                    operation.description =
+                        format!("{}: {}", operation.description, word.all_bases(value));
                 }
+                return Ok(ASTNode::Integer(BigInt::from_i128(value)));
             }
-            _ => Err("Unknown function".to_string()),
+        };
+        if overflow {
+            changes.warnings.push(format!(
+                "{} overflows {} and wraps to {}",
+                shown,
+                word,
+                word.format(result, self.settings.base)
+            ));
         }
+        let description = format!("{}: {}", description, word.all_bases(result));
+        changes.record(rule, description, path);
+        Ok(ASTNode::Integer(BigInt::from_i128(result)))
     }
-    fn ast_to_string(ast: &ASTNode) -> String {
+
+    /// Whether the operation of `ast` is a comparison or `and`, `or` or
+    /// `not`, which give booleans.
+    fn is_logical(ast: &ASTNode) -> bool {
         match ast {
-            ASTNode::Number(value) => format!("{}", value),
-            ASTNode::Pi => "π".to_string(),
-            ASTNode::Euler => "e".to_string(),
-            ASTNode::BinaryOp { left, op, right } => {
-                let left_str = Self::ast_to_string(left);
-                let right_str = Self::ast_to_string(right);
-                let op_str = match op {
-                    Token::Plus => "+",
-                    Token::Minus => "-",
-                    Token::Multiply => "*",
-                    Token::Divide => "/",
-                    Token::Exponent => "^",
-                    _ => "Unknown binary operator",
+            ASTNode::BinaryOp { op, .. } => matches!(
+                op,
+                Token::Equal
+                    | Token::NotEqual
+                    | Token::Less
+                    | Token::LessEqual
+                    | Token::Greater
+                    | Token::GreaterEqual
+                    | Token::And
+                    | Token::Or
+            ),
+            ASTNode::UnaryOp { op, .. } => *op == Token::Not,
+            _ => false,
+        }
+    }
+
+    /// Rejects booleans as operands of arithmetic, angles and built-in
+    /// functions, and numbers as operands of `and`, `or` and `not`. `==`
+    /// and `!=` also compare two booleans.
+    fn check_operands(&self, ast: &ASTNode) -> Result<(), EvalErrorKind> {
+        let (operation, operands) = match ast {
+            ASTNode::BinaryOp {
+                left, op, right, ..
+            } => (format!("`{}`", self.operator(op)), vec![&**left, &**right]),
+            ASTNode::UnaryOp { op, operand, .. } => {
+                let symbol = match op {
+                    Token::Not => "not",
+                    Token::Fact => "!",
+                    Token::DoubleFact => "!!",
+                    Token::Subfact => "!",
+                    Token::BitNot => "~",
+                    Token::Percent => "%",
+                    _ => "-",
                 };
-                format!("{} {} {}", left_str, op_str, right_str)
+                (format!("`{}`", symbol), vec![&**operand])
             }
-            ASTNode::UnaryOp { op, operand } => {
-                let operand_str = Self::ast_to_string(operand);
-                match op {
-                    Token::Minus => format!("-{}", operand_str),
-                    Token::Fact => format!("{}!", operand_str),
-                    _ => "Unknown unary operator".to_string(),
+            ASTNode::Angle { value, unit, .. } => (
+                format!("`{}`", unit.suffix(self.settings.output).trim()),
+                vec![&**value],
+            ),
+            ASTNode::Call { name, args, .. } => match self.environment.builtins().get(name) {
+                Some(builtin) => (
+                    builtin
+                        .display_name(self.settings.function_names)
+                        .to_string(),
+                    args.iter().collect(),
+                ),
+                // A defined function may use a boolean argument any way its
+                // body does.
+                None => return Ok(()),
+            },
+            _ => return Ok(()),
+        };
+        let booleans = operands
+            .iter()
+            .filter(|operand| matches!(operand, ASTNode::Boolean(_)))
+            .count();
+        let logic = match ast {
+            ASTNode::BinaryOp {
+                op: Token::And | Token::Or,
+                ..
+            }
+            | ASTNode::UnaryOp { op: Token::Not, .. } => true,
+            ASTNode::BinaryOp {
+                op: Token::Equal | Token::NotEqual,
+                ..
+            } if booleans == operands.len() => return Ok(()),
+            _ => false,
+        };
+        for operand in operands {
+            match (logic, operand) {
+                (false, ASTNode::Boolean(value)) => {
+                    return Err(EvalErrorKind::NotANumber {
+                        operation,
+                        value: *value,
+                    })
+                }
+                (true, ASTNode::Boolean(_)) | (false, _) => {}
+                (true, _) => {
+                    return Err(EvalErrorKind::NotABoolean {
+                        operation: match ast {
+                            ASTNode::BinaryOp { op: Token::And, .. } => "and",
+                            ASTNode::BinaryOp { op: Token::Or, .. } => "or",
+                            _ => "not",
+                        },
+                        value: Self::operand_value(operand),
+                    })
                 }
             }
-            ASTNode::Function { func, argument } => {
-                let arg_str = Self::ast_to_string(argument);
-                let func_str = match func {
-                    Token::Abs => "abs",
-                    Token::Sqrt => "sqrt",
-                    Token::Ln => "ln",
-                    Token::Sin => "sin",
-                    Token::Cos => "cos",
-                    Token::Tg => "tg",
-                    Token::Cotg => "cotg",
-                    Token::Sec => "sec",
-                    Token::Csc => "csc",
-                    Token::Asin => "asin",
-                    Token::Acos => "acos",
-                    Token::Atg => "atg",
-                    Token::Actg => "actg",
-                    _ => "Unknown function",
+        }
+        Ok(())
+    }
+
+    /// Applies a comparison, `and`, `or` or `not` to operands that passed
+    /// `check_operands`.
+    fn apply_logic(&self, ast: ASTNode, path: &[usize], changes: &mut Changes) -> ASTNode {
+        let shown = self.ast_to_string(&ast);
+        let (result, rule, remark) = match &ast {
+            ASTNode::UnaryOp { operand, .. } => {
+                (**operand != ASTNode::Boolean(true), Rule::Logic, None)
+            }
+            ASTNode::BinaryOp {
+                left,
+                op: op @ (Token::And | Token::Or),
+                right,
+                ..
+            } => {
+                let (ASTNode::Boolean(left), ASTNode::Boolean(right)) = (&**left, &**right) else {
+                    unreachable!("checked by `check_operands`");
+                };
+                let result = if *op == Token::And {
+                    *left && *right
+                } else {
+                    *left || *right
+                };
+                (result, Rule::Logic, None)
+            }
+            ASTNode::BinaryOp {
+                left, op, right, ..
+            } => {
+                let (ordering, difference) = self.compare(left, right);
+                let result = match op {
+                    Token::Equal => ordering == Ordering::Equal,
+                    Token::NotEqual => ordering != Ordering::Equal,
+                    Token::Less => ordering == Ordering::Less,
+                    Token::LessEqual => ordering != Ordering::Greater,
+                    Token::Greater => ordering == Ordering::Greater,
+                    _ => ordering != Ordering::Less,
                 };
-                format!("{}({})", func_str, arg_str)
+                let remark = difference.map(|difference| {
+                    format!(
+                        " (they differ by {:.1e}, within the tolerance {:e})",
+                        difference, self.settings.tolerance
+                    )
+                });
+                (result, Rule::Comparison, remark)
             }
-            ASTNode::LogBase { base, number } => {
-                let base_str = Self::ast_to_string(base);
-                let number_str = Self::ast_to_string(number);
+            _ => unreachable!("only called for logical operations"),
+        };
+        let node = ASTNode::Boolean(result);
+        let description = format!(
+            "{} is {}{}",
+            shown,
+            self.ast_to_string(&node),
+            remark.unwrap_or_default()
+        );
+        changes.record(rule, description, path);
+        node
+    }
 
-                format!("log({},{})", base_str, number_str)
+    /// Orders two evaluated operands. Fractions and big integers are
+    /// compared exactly; floats count as equal when they differ by at most
+    /// the tolerance relative to the larger of them and 1, and the
+    /// difference is then returned too if it is not zero.
+    fn compare(&self, left: &ASTNode, right: &ASTNode) -> (Ordering, Option<f64>) {
+        match (left, right) {
+            (ASTNode::Boolean(left), ASTNode::Boolean(right)) => return (left.cmp(right), None),
+            (ASTNode::Fraction(left), ASTNode::Fraction(right)) => {
+                let numerators = left.common_denominator(right).and_then(|denominator| {
+                    Some((
+                        left.expanded_to(denominator)?.numerator(),
+                        right.expanded_to(denominator)?.numerator(),
+                    ))
+                });
+                if let Some((left, right)) = numerators {
+                    return (left.cmp(&right), None);
+                }
             }
-            ASTNode::Grouping(expression) => {
-                format!("({})", Self::ast_to_string(expression))
+            (ASTNode::Integer(_), _) | (_, ASTNode::Integer(_)) => {
+                if let (Some(left), Some(right)) = (Self::integer_of(left), Self::integer_of(right))
+                {
+                    let difference = &left - &right;
+                    let ordering = if difference == BigInt::zero() {
+                        Ordering::Equal
+                    } else if difference.is_negative() {
+                        Ordering::Less
+                    } else {
+                        Ordering::Greater
+                    };
+                    return (ordering, None);
+                }
             }
+            _ => {}
+        }
+        let (a, b) = (Self::operand_value(left), Self::operand_value(right));
+        let difference = (a - b).abs();
+        if difference <= self.settings.tolerance * a.abs().max(b.abs()).max(1.0) {
+            (Ordering::Equal, (difference > 0.0).then_some(difference))
+        } else {
+            (a.total_cmp(&b), None)
         }
     }
-    fn truncate_number(value: f64) -> f64 {
-        (value * 100.0).round() / 100.0
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::lexer::Token;
-    use crate::parser::ASTNode;
 
-    #[test]
-    fn test_basic_arithmetic() {
-        let mut evaluator = Evaluator::new();
+    /// The node of `ast` with the span `span`, so an error can show the
+    /// failing subexpression before any of it was reduced.
+    fn node_at(ast: &ASTNode, span: Span) -> Option<&ASTNode> {
+        if span != Span::default() && Self::span_of(ast) == span {
+            return Some(ast);
+        }
+        match ast {
+            ASTNode::BinaryOp { left, right, .. } => {
+                Self::node_at(left, span).or_else(|| Self::node_at(right, span))
+            }
+            ASTNode::UnaryOp { operand, .. }
+            | ASTNode::Angle { value: operand, .. }
+            | ASTNode::Grouping(operand) => Self::node_at(operand, span),
+            ASTNode::Call { args, .. } => args.iter().find_map(|arg| Self::node_at(arg, span)),
+            _ => None,
+        }
+    }
 
-        let ast = ASTNode::BinaryOp {
-            left: Box::new(ASTNode::Number(5.0)),
-            op: Token::Plus,
-            right: Box::new(ASTNode::Number(3.0)),
-        };
-        let result = evaluator.evaluate_and_print(ast).unwrap();
-        assert_eq!(result, 8.0);
+    /// The span of a node, for errors in its operation.
+    fn span_of(ast: &ASTNode) -> Span {
+        match ast {
+            ASTNode::BinaryOp { span, .. }
+            | ASTNode::UnaryOp { span, .. }
+            | ASTNode::Call { span, .. }
+            | ASTNode::Variable { span, .. }
+            | ASTNode::Angle { span, .. } => *span,
+            _ => Span::default(),
+        }
+    }
 
-        let ast = ASTNode::BinaryOp {
-            left: Box::new(ASTNode::Number(5.0)),
-            op: Token::Multiply,
-            right: Box::new(ASTNode::Number(3.0)),
-        };
-        let result = evaluator.evaluate_and_print(ast).unwrap();
-        assert_eq!(result, 15.0);
+    /// The value of an evaluated operand in the integer type `word`.
+    fn word_value(word: Word, ast: &ASTNode) -> Result<i128, EvalErrorKind> {
+        match Self::integer_of(ast) {
+            Some(integer) => Ok(word.wrap(integer.wrapping_to_i128())),
+            None => Err(EvalErrorKind::NotAnInteger {
+                function: "programmer mode",
+                value: Self::operand_value(ast),
+            }),
+        }
     }
 
-    #[test]
-    fn test_trigonometric_functions() {
-        let mut evaluator = Evaluator::new();
+    /// `a op b` in the integer type `word`, and whether it overflowed.
+    /// Division truncates toward zero, and right shifts of signed types
+    /// copy the sign bit, as in C.
+    fn word_binary(
+        word: Word,
+        a: i128,
+        op: &Token,
+        b: i128,
+    ) -> Result<(i128, bool), EvalErrorKind> {
+        let shift = || {
+            if (0..word.bits as i128).contains(&b) {
+                Ok(b as u32)
+            } else {
+                Err(EvalErrorKind::ShiftOutOfRange {
+                    amount: b,
+                    bits: word.bits,
+                })
+            }
+        };
+        // Operands have at most 64 bits, so only a product can leave the
+        // range of `i128`; `None` then stands for an overflow.
+        let exact = match op {
+            Token::Plus => Some(a + b),
+            Token::Minus => Some(a - b),
+            Token::Multiply => a.checked_mul(b),
+            Token::Divide | Token::IntDivide | Token::Modulo if b == 0 => {
+                return Err(EvalErrorKind::DivisionByZero)
+            }
+            Token::Divide => Some(a / b),
+            Token::IntDivide | Token::Modulo => {
+                // Round the quotient down rather than toward zero.
+                let mut quotient = a / b;
+                if a % b != 0 && (a < 0) != (b < 0) {
+                    quotient -= 1;
+                }
+                Some(if *op == Token::Modulo {
+                    a - b * quotient
+                } else {
+                    quotient
+                })
+            }
+            Token::Exponent => {
+                if b < 0 {
+                    return Err(EvalErrorKind::DomainError {
+                        function: "whole-number power",
+                        value: b as f64,
+                    });
+                }
+                let mut result = 1i128;
+                let mut base = a;
+                let mut exponent = b;
+                while exponent > 0 {
+                    if exponent & 1 == 1 {
+                        result = word.wrap(result.wrapping_mul(base));
+                    }
+                    base = word.wrap(base.wrapping_mul(base));
+                    exponent >>= 1;
+                }
+                let exact = u32::try_from(b).ok().and_then(|b| a.checked_pow(b));
+                return Ok((result, exact != Some(result)));
+            }
+            Token::BitAnd => return Ok((a & b, false)),
+            Token::BitOr => return Ok((a | b, false)),
+            Token::BitXor => return Ok((a ^ b, false)),
+            Token::ShiftLeft => return Ok((word.wrap(a << shift()?), false)),
+            Token::ShiftRight => return Ok((a >> shift()?, false)),
+            _ => return Err(EvalErrorKind::UnknownOperation),
+        };
+        let result = word.wrap(exact.unwrap_or_else(|| a.wrapping_mul(b)));
+        Ok((result, exact != Some(result)))
+    }
+
+    /// Wraps whole-number literals into the integer type of the programmer
+    /// mode. A `0x`, `0o` or `0b` literal is a bit pattern, so `0xff` is -1
+    /// as an `i8`; a decimal literal must be in the range of the type, like
+    /// the `128` of `-128` for an `i8`. A literal that does not fit is an
+    /// error at `span`, the innermost operation around it.
+    fn word_literals(word: Word, ast: ASTNode, span: Span) -> Result<ASTNode, EvalError> {
+        let inner = Self::span_of(&ast);
+        let span = if inner == Span::default() {
+            span
+        } else {
+            inner
+        };
+        let wrap = |node: Box<ASTNode>| Self::word_literals(word, *node, span).map(Box::new);
+        Ok(match ast {
+            ASTNode::Number(_) | ASTNode::Integer(_) => {
+                let Some(integer) = Self::integer_of(&ast) else {
+                    return Ok(ast);
+                };
+                let exact = integer.wrapping_to_i128();
+                let fits = match ast {
+                    ASTNode::Integer(_) => (0..1i128 << word.bits).contains(&exact),
+                    _ => word.contains(exact),
+                };
+                if !fits || BigInt::from_i128(exact) != integer {
+                    return Err(EvalError {
+                        kind: EvalErrorKind::OutOfRange {
+                            value: integer.to_string(),
+                            word,
+                        },
+                        span,
+                        expression: integer.to_string(),
+                        step: 1,
+                    });
+                }
+                ASTNode::Integer(BigInt::from_i128(word.wrap(exact)))
+            }
+            ASTNode::BinaryOp {
+                left,
+                op,
+                right,
+                span,
+            } => ASTNode::BinaryOp {
+                left: wrap(left)?,
+                op,
+                right: wrap(right)?,
+                span,
+            },
+            ASTNode::UnaryOp {
+                op: Token::Minus,
+                operand,
+                span,
+            } => {
+                // The smallest value of a signed type, such as -128 for an
+                // `i8`, has no positive counterpart to negate.
+                let smallest = match *operand {
+                    ASTNode::Number(_) => Self::integer_of(&operand)
+                        .map(|integer| integer.wrapping_to_i128())
+                        .filter(|&exact| !word.contains(exact) && word.contains(-exact)),
+                    _ => None,
+                };
+                match smallest {
+                    Some(exact) => ASTNode::Integer(BigInt::from_i128(-exact)),
+                    None => ASTNode::UnaryOp {
+                        op: Token::Minus,
+                        operand: wrap(operand)?,
+                        span,
+                    },
+                }
+            }
+            ASTNode::UnaryOp { op, operand, span } => ASTNode::UnaryOp {
+                op,
+                operand: wrap(operand)?,
+                span,
+            },
+            ASTNode::Angle { value, unit, span } => ASTNode::Angle {
+                value: wrap(value)?,
+                unit,
+                span,
+            },
+            ASTNode::Grouping(expression) => ASTNode::Grouping(wrap(expression)?),
+            ASTNode::Call { name, args, span } => ASTNode::Call {
+                name,
+                args: args
+                    .into_iter()
+                    .map(|arg| Self::word_literals(word, arg, span))
+                    .collect::<Result<_, _>>()?,
+                span,
+            },
+            ASTNode::Pi
+            | ASTNode::Euler
+            | ASTNode::Fraction(_)
+            | ASTNode::Boolean(_)
+            | ASTNode::Variable { .. } => ast,
+        })
+    }
+
+    /// Evaluates a built-in function of arguments that are all numbers,
+    /// converting angles between the angle mode and radians.
+    fn call_builtin(&self, builtin: &Builtin, args: &[ASTNode]) -> Result<f64, EvalErrorKind> {
+        if !builtin.arity.accepts(args.len()) {
+            return Err(EvalErrorKind::WrongArity {
+                name: builtin
+                    .display_name(self.settings.function_names)
+                    .to_string(),
+                expected: builtin.arity,
+                got: args.len(),
+            });
+        }
+        let angle_mode = self.settings.angle_mode;
+        let values: Vec<f64> = args.iter().map(Self::operand_value).collect();
+        let inputs: Vec<f64> = match builtin.angles {
+            Angles::Argument => values
+                .iter()
+                .map(|&value| angle_mode.to_radians(value))
+                .collect(),
+            _ => values.clone(),
+        };
+        if let Some(index) = (builtin.domain)(&inputs) {
+            return Err(EvalErrorKind::DomainError {
+                function: builtin.display_name(self.settings.function_names),
+                value: values[index],
+            });
+        }
+        let result = (builtin.implementation)(&inputs);
+        let result = match builtin.angles {
+            Angles::Result => angle_mode.radians_to(result),
+            _ => result,
+        };
+        Self::finite(result, builtin.rule)
+    }
+
+    /// Replaces the parameters of a function body with the arguments of a
+    /// call. The spans of the body point into its definition, so they are
+    /// all replaced with `span`, the call, for errors inside the body.
+    fn substitute(ast: ASTNode, arguments: &HashMap<&str, ASTNode>, span: Span) -> ASTNode {
+        let substitute = |node: Box<ASTNode>| Box::new(Self::substitute(*node, arguments, span));
+        match ast {
+            ASTNode::Variable { name, .. } => match arguments.get(name.as_str()) {
+                Some(value) => value.clone(),
+                None => ASTNode::Variable { name, span },
+            },
+            ASTNode::BinaryOp {
+                left, op, right, ..
+            } => ASTNode::BinaryOp {
+                left: substitute(left),
+                op,
+                right: substitute(right),
+                span,
+            },
+            ASTNode::UnaryOp { op, operand, .. } => ASTNode::UnaryOp {
+                op,
+                operand: substitute(operand),
+                span,
+            },
+            ASTNode::Angle { value, unit, .. } => ASTNode::Angle {
+                value: substitute(value),
+                unit,
+                span,
+            },
+            ASTNode::Call { name, args, .. } => ASTNode::Call {
+                name,
+                args: args
+                    .into_iter()
+                    .map(|arg| Self::substitute(arg, arguments, span))
+                    .collect(),
+                span,
+            },
+            ASTNode::Grouping(expression) => ASTNode::Grouping(substitute(expression)),
+            ASTNode::Number(_)
+            | ASTNode::Fraction(_)
+            | ASTNode::Integer(_)
+            | ASTNode::Boolean(_)
+            | ASTNode::Pi
+            | ASTNode::Euler => ast,
+        }
+    }
+
+    /// Rejects infinite and NaN results, naming the operation that produced
+    /// them.
+    fn finite(result: f64, operation: Rule) -> Result<f64, EvalErrorKind> {
+        if result.is_finite() {
+            Ok(result)
+        } else {
+            Err(EvalErrorKind::NotFinite { operation })
+        }
+    }
+
+    /// The exponent as a fraction with an odd denominator, e.g. `1/3` or
+    /// `0.2 = 1/5`. A negative base has a real root for such exponents.
+    fn odd_root_exponent(exponent: &ASTNode) -> Option<Rational> {
+        let fraction = match exponent {
+            ASTNode::Fraction(fraction) => fraction.reduced(),
+            ASTNode::Number(value) => Rational::approximate(*value, 1000)?,
+            _ => return None,
+        };
+        (!fraction.is_integer() && fraction.denominator() % 2 == 1).then_some(fraction)
+    }
+
+    fn describe_binary(&self, left: &ASTNode, op: &Token, right: &ASTNode) -> (Rule, String) {
+        let (dividend, divisor) = (Self::operand_value(left), Self::operand_value(right));
+        let left = self.ast_to_string(left);
+        let right = self.ast_to_string(right);
+        // `7 = 2 * 3 + 1`, which explains both `7 // 3` and `7 mod 3`.
+        let quotient = (dividend / divisor).floor();
+        let show = |value: f64| self.ast_to_string(&ASTNode::Number(value));
+        let division = || {
+            format!(
+                "since {} = {} {} {} + {}",
+                left,
+                show(quotient),
+                self.operator(&Token::Multiply),
+                right,
+                show(dividend - divisor * quotient)
+            )
+        };
+        match op {
+            Token::Plus => (Rule::Addition, format!("add {} + {}", left, right)),
+            Token::Minus => (Rule::Subtraction, format!("subtract {} - {}", left, right)),
+            Token::Multiply => (
+                Rule::Multiplication,
+                format!("multiply {} {} {}", left, self.spoken_operator(op), right),
+            ),
+            Token::Divide => (
+                Rule::Division,
+                format!("divide {} {} {}", left, self.spoken_operator(op), right),
+            ),
+            Token::IntDivide => (
+                Rule::IntegerDivision,
+                format!("{} // {} = {}, {}", left, right, show(quotient), division()),
+            ),
+            Token::Modulo => (
+                Rule::Modulo,
+                format!(
+                    "{} mod {} = {}, {}",
+                    left,
+                    right,
+                    show(dividend - divisor * quotient),
+                    division()
+                ),
+            ),
+            Token::BitAnd => (Rule::Bitwise, format!("and {} & {}", left, right)),
+            Token::BitOr => (Rule::Bitwise, format!("or {} | {}", left, right)),
+            Token::BitXor => (
+                Rule::Bitwise,
+                format!("exclusive-or {} xor {}", left, right),
+            ),
+            Token::ShiftLeft => (Rule::Shift, format!("shift {} left by {}", left, right)),
+            Token::ShiftRight => (Rule::Shift, format!("shift {} right by {}", left, right)),
+            _ => (
+                Rule::Power,
+                format!("raise {} to the power {}", left, right),
+            ),
+        }
+    }
+
+    fn evaluate_binary_op(left: f64, op: &Token, right: f64) -> Result<f64, EvalErrorKind> {
+        match op {
+            Token::Plus => Ok(left + right),
+            Token::Minus => Ok(left - right),
+            Token::Multiply => Ok(left * right),
+            Token::Divide => {
+                if right == 0.0 {
+                    Err(EvalErrorKind::DivisionByZero)
+                } else {
+                    Ok(left / right)
+                }
+            }
+            Token::IntDivide | Token::Modulo if right == 0.0 => Err(EvalErrorKind::DivisionByZero),
+            Token::IntDivide => Ok((left / right).floor()),
+            Token::Modulo => Ok(left - right * (left / right).floor()),
+            Token::Exponent => Ok(left.powf(right)),
+            Token::BitAnd => Err(EvalErrorKind::ProgrammerOnly { operator: "&" }),
+            Token::BitOr => Err(EvalErrorKind::ProgrammerOnly { operator: "|" }),
+            Token::BitXor => Err(EvalErrorKind::ProgrammerOnly { operator: "xor" }),
+            Token::ShiftLeft => Err(EvalErrorKind::ProgrammerOnly { operator: "<<" }),
+            Token::ShiftRight => Err(EvalErrorKind::ProgrammerOnly { operator: ">>" }),
+            _ => Err(EvalErrorKind::UnknownOperation),
+        }
+    }
+
+    /// Whole-number factorials never get here; they are computed exactly.
+    fn evaluate_unary_op(
+        op: &Token,
+        operand: f64,
+        gamma_factorial: bool,
+    ) -> Result<f64, EvalErrorKind> {
+        match op {
+            Token::Minus => Ok(-operand),
+            Token::Fact if gamma_factorial => Ok(gamma(operand + 1.0)),
+            Token::Fact | Token::DoubleFact | Token::Subfact => Err(EvalErrorKind::NotAnInteger {
+                function: Self::factorial_name(op),
+                value: operand,
+            }),
+            Token::Percent => Ok(operand / 100.0),
+            Token::BitNot => Err(EvalErrorKind::ProgrammerOnly { operator: "~" }),
+            _ => Err(EvalErrorKind::UnknownOperation),
+        }
+    }
+
+    fn ast_to_string(&self, ast: &ASTNode) -> String {
+        if let (Some(word), ASTNode::Number(_) | ASTNode::Integer(_)) =
+            (self.settings.programmer, ast)
+        {
+            if let Some(integer) = Self::integer_of(ast) {
+                let value = word.wrap(integer.wrapping_to_i128());
+                return word.format(value, self.settings.base);
+            }
+        }
+        match ast {
+            ASTNode::Number(value) => self.settings.precision.format(*value),
+            ASTNode::Fraction(fraction) => fraction.to_string(),
+            ASTNode::Integer(integer) => integer.to_string(),
+            ASTNode::Boolean(value) => value.to_string(),
+            ASTNode::Pi => match self.settings.output {
+                Output::Ascii => "pi".to_string(),
+                Output::Unicode | Output::Math => "π".to_string(),
+            },
+            ASTNode::Euler => "e".to_string(),
+            ASTNode::Variable { name, .. } => name.clone(),
+            ASTNode::BinaryOp {
+                left, op, right, ..
+            } => {
+                let left_str = self.operand_to_string(left, op, false);
+                let right_str = self.operand_to_string(right, op, true);
+                if *op == Token::Exponent && self.settings.output == Output::Math {
+                    if let Some(power) = superscript(&right_str) {
+                        return format!("{}{}", left_str, power);
+                    }
+                }
+                format!("{} {} {}", left_str, self.operator(op), right_str)
+            }
+            ASTNode::UnaryOp { op, operand, .. } => {
+                let operand_str = self.ast_to_string(operand);
+                match op {
+                    // `-(-5)`, never `--5`
+                    Token::Minus if operand_str.starts_with('-') => format!("-({})", operand_str),
+                    Token::Minus => format!("-{}", operand_str),
+                    Token::Fact => format!("{}!", operand_str),
+                    Token::DoubleFact => format!("{}!!", operand_str),
+                    Token::Subfact => format!("!{}", operand_str),
+                    Token::BitNot => format!("~{}", operand_str),
+                    Token::Percent => format!("{}%", operand_str),
+                    Token::Not => format!("not {}", operand_str),
+                    _ => "Unknown unary operator".to_string(),
+                }
+            }
+            ASTNode::Grouping(expression) => {
+                format!("({})", self.ast_to_string(expression))
+            }
+            ASTNode::Call { name, args, .. } => {
+                let builtin = self.environment.builtins().get(name);
+                let angle_mode = self.settings.angle_mode;
+                let args: Vec<String> = args
+                    .iter()
+                    .map(|arg| {
+                        let mut arg_str = self.ast_to_string(arg);
+                        // Plain numbers are angles in the angle mode; mark them.
+                        if builtin.is_some_and(|builtin| builtin.angles == Angles::Argument)
+                            && matches!(arg, ASTNode::Number(_))
+                            && angle_mode != AngleMode::Radians
+                        {
+                            arg_str.push_str(angle_mode.suffix(self.settings.output));
+                        }
+                        arg_str
+                    })
+                    .collect();
+                match builtin {
+                    Some(Builtin {
+                        symbol: Some(symbol),
+                        ..
+                    }) if self.settings.output == Output::Math => {
+                        format!("{}({})", symbol, args.join(", "))
+                    }
+                    Some(builtin) => {
+                        let name = builtin.display_name(self.settings.function_names);
+                        (builtin.display)(name, &args)
+                    }
+                    None => call_notation(name, &args),
+                }
+            }
+            ASTNode::Angle { value, unit, .. } => {
+                let suffix = unit.suffix(self.settings.output);
+                format!("{}{}", self.ast_to_string(value), suffix)
+            }
+        }
+    }
+    /// The symbol of a binary operator in the output mode.
+    fn operator(&self, op: &Token) -> &'static str {
+        match (op, self.settings.output) {
+            (Token::Plus, _) => "+",
+            (Token::Minus, _) => "-",
+            (Token::Multiply, Output::Math) => "×",
+            (Token::Multiply, _) => "*",
+            (Token::Divide, Output::Math) => "÷",
+            (Token::Divide, _) => "/",
+            (Token::IntDivide, _) => "//",
+            (Token::Modulo, _) => "mod",
+            (Token::Exponent, _) => "^",
+            (Token::BitAnd, _) => "&",
+            (Token::BitOr, _) => "|",
+            (Token::BitXor, _) => "xor",
+            (Token::ShiftLeft, _) => "<<",
+            (Token::ShiftRight, _) => ">>",
+            (Token::Equal, _) => "==",
+            (Token::NotEqual, Output::Math) => "≠",
+            (Token::NotEqual, _) => "!=",
+            (Token::Less, _) => "<",
+            (Token::LessEqual, Output::Math) => "≤",
+            (Token::LessEqual, _) => "<=",
+            (Token::Greater, _) => ">",
+            (Token::GreaterEqual, Output::Math) => "≥",
+            (Token::GreaterEqual, _) => ">=",
+            (Token::And, _) => "and",
+            (Token::Or, _) => "or",
+            _ => "Unknown binary operator",
+        }
+    }
+    /// The symbol of `*` or `/` in the description of a step, which keeps
+    /// `×` and `÷` unless the output is plain ASCII.
+    fn spoken_operator(&self, op: &Token) -> &'static str {
+        match (op, self.settings.output) {
+            (_, Output::Ascii) => self.operator(op),
+            (Token::Multiply, _) => "×",
+            (Token::Divide, _) => "÷",
+            _ => self.operator(op),
+        }
+    }
+    /// Wraps an operand of `parent` in parentheses when the tree binds it
+    /// differently than reading the flat string would, e.g. the implicit
+    /// product in `1 / (2 * π)` or a negative base in `(-2) ^ 2`.
+    fn operand_to_string(&self, operand: &ASTNode, parent: &Token, right_side: bool) -> String {
+        let operand_str = self.ast_to_string(operand);
+        let needs_parentheses = match operand {
+            ASTNode::BinaryOp { op, .. } => {
+                let precedence = Parser::get_precedence(op);
+                let parent_precedence = Parser::get_precedence(parent);
+                let binds_right = Parser::get_associativity(parent) == Associativity::Right;
+                precedence < parent_precedence
+                    || (precedence == parent_precedence && right_side != binds_right)
+            }
+            ASTNode::Number(value) => *parent == Token::Exponent && !right_side && *value < 0.0,
+            ASTNode::Integer(integer) => {
+                *parent == Token::Exponent && !right_side && integer.is_negative()
+            }
+            ASTNode::Fraction(fraction) => {
+                !fraction.is_integer() && matches!(parent, Token::Divide | Token::Exponent)
+                    || (*parent == Token::Exponent && !right_side && fraction.numerator() < 0)
+            }
+            ASTNode::UnaryOp {
+                op: Token::Minus, ..
+            } => *parent == Token::Exponent && !right_side,
+            _ => false,
+        };
+        if needs_parentheses {
+            format!("({})", operand_str)
+        } else {
+            operand_str
+        }
+    }
+}
+
+/// Writes a whole-number exponent such as `2` or `-3` as superscripts, or
+/// returns `None` for any other exponent.
+fn superscript(exponent: &str) -> Option<String> {
+    let digits = exponent.strip_prefix('-').unwrap_or(exponent);
+    if digits.is_empty() || !digits.bytes().all(|digit| digit.is_ascii_digit()) {
+        return None;
+    }
+    let mut power = String::new();
+    if digits.len() < exponent.len() {
+        power.push('⁻');
+    }
+    for digit in digits.bytes() {
+        power.push(['⁰', '¹', '²', '³', '⁴', '⁵', '⁶', '⁷', '⁸', '⁹'][(digit - b'0') as usize]);
+    }
+    Some(power)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::environment::UserFunction;
+    use crate::lexer::{Lexer, Span, Token};
+    use crate::parser::ASTNode;
+    use crate::settings::{Base, FunctionNames};
 
-        let ast = ASTNode::Function {
-            func: Token::Sin,
-            argument: Box::new(ASTNode::Number(30.0)), // sin(30°) = 0.5
+    #[test]
+    fn test_basic_arithmetic() {
+        let evaluator = Evaluator::new();
+
+        let ast = ASTNode::BinaryOp {
+            left: Box::new(ASTNode::Number(5.0)),
+            op: Token::Plus,
+            right: Box::new(ASTNode::Number(3.0)),
+            span: Span::default(),
         };
-        let result = evaluator.evaluate_and_print(ast).unwrap();
-        assert_eq!(result, 0.5);
+        let result = evaluator.evaluate(ast).unwrap().result;
+        assert_eq!(result, 8.0);
 
-        let ast = ASTNode::Function {
-            func: Token::Cos,
-            argument: Box::new(ASTNode::Number(60.0)), // cos(60°) = 0.5
+        let ast = ASTNode::BinaryOp {
+            left: Box::new(ASTNode::Number(5.0)),
+            op: Token::Multiply,
+            right: Box::new(ASTNode::Number(3.0)),
+            span: Span::default(),
         };
-        let result = evaluator.evaluate_and_print(ast).unwrap();
-        assert_eq!(result, 0.5);
+        let result = evaluator.evaluate(ast).unwrap().result;
+        assert_eq!(result, 15.0);
+    }
+
+    #[test]
+    fn test_trigonometric_functions() {
+        let evaluator = Evaluator::new();
+
+        let ast = call("sin", vec![ASTNode::Number(30.0)]);
+        let result = evaluator.evaluate(ast).unwrap().result;
+        assert!((result - 0.5).abs() < 1e-12);
+
+        let ast = call("cos", vec![ASTNode::Number(60.0)]);
+        let result = evaluator.evaluate(ast).unwrap().result;
+        assert!((result - 0.5).abs() < 1e-12);
     }
 
     #[test]
     fn test_unary_operations() {
-        let mut evaluator = Evaluator::new();
+        let evaluator = Evaluator::new();
 
         let ast = ASTNode::UnaryOp {
             op: Token::Minus,
             operand: Box::new(ASTNode::Number(7.0)),
+            span: Span::default(),
         };
-        assert_eq!(evaluator.evaluate_and_print(ast).unwrap(), -7.0);
+        assert_eq!(evaluator.evaluate(ast).unwrap().result, -7.0);
 
         let ast = ASTNode::UnaryOp {
             op: Token::Fact,
             operand: Box::new(ASTNode::Number(5.0)),
+            span: Span::default(),
         };
-        assert_eq!(evaluator.evaluate_and_print(ast).unwrap(), 120.0);
+        assert_eq!(evaluator.evaluate(ast).unwrap().result, 120.0);
     }
 
     #[test]
     fn test_constants() {
-        let mut evaluator = Evaluator::new();
+        let evaluator = Evaluator::new();
 
-        let ast = ASTNode::Pi;
-        assert_eq!(evaluator.evaluate_and_print(ast).unwrap(), 3.14);
+        let trace = evaluator.evaluate(ASTNode::Pi).unwrap();
+        assert_eq!(trace.lines().pop().unwrap(), "= 3.14");
+        assert_eq!(trace.result, PI);
 
-        let ast = ASTNode::Euler;
-        assert_eq!(evaluator.evaluate_and_print(ast).unwrap(), 2.72);
+        let trace = evaluator.evaluate(ASTNode::Euler).unwrap();
+        assert_eq!(trace.lines().pop().unwrap(), "= 2.72");
+        assert_eq!(trace.result, E);
     }
 
     #[test]
     fn test_nested_expressions() {
-        let mut evaluator = Evaluator::new();
+        let evaluator = Evaluator::new();
 
         let ast = ASTNode::BinaryOp {
             left: Box::new(ASTNode::Number(3.0)),
@@ -394,45 +2032,1126 @@ mod tests {
                 left: Box::new(ASTNode::Number(4.0)),
                 op: Token::Multiply,
                 right: Box::new(ASTNode::Number(2.0)),
+                span: Span::default(),
             }),
+            span: Span::default(),
         };
         // 3 + (4 * 2) = 3 + 8 = 11
-        assert_eq!(evaluator.evaluate_and_print(ast).unwrap(), 11.0);
+        assert_eq!(evaluator.evaluate(ast).unwrap().result, 11.0);
     }
     #[test]
     fn test_edge_case_trigonometric() {
-        let mut evaluator = Evaluator::new();
+        let evaluator = Evaluator::new();
 
         //test pentru tg unde a aprope de infint
-        let ast = ASTNode::Function {
-            func: Token::Tg,
-            argument: Box::new(ASTNode::Number(89.999)),
-        };
-        let result = evaluator.evaluate_and_print(ast).unwrap();
+        let ast = call("tg", vec![ASTNode::Number(89.999)]);
+        let result = evaluator.evaluate(ast).unwrap().result;
         assert!(result.is_finite());
 
         // Test pentru cotg unde este aproape 0
-        let ast = ASTNode::Function {
-            func: Token::Cotg,
-            argument: Box::new(ASTNode::Number(179.999)),
-        };
-        let result = evaluator.evaluate_and_print(ast).unwrap();
+        let ast = call("cotg", vec![ASTNode::Number(179.999)]);
+        let result = evaluator.evaluate(ast).unwrap().result;
         assert!(result.is_finite());
     }
     #[test]
-    #[should_panic(expected = "Can't divide number by 0")]
     fn test_division_by_zero() {
-        let mut evaluator = Evaluator::new();
+        let evaluator = Evaluator::new();
 
+        // 5 / (2 - 2)
         let ast = ASTNode::BinaryOp {
             left: Box::new(ASTNode::Number(5.0)),
             op: Token::Divide,
-            right: Box::new(ASTNode::Number(0.0)),
+            right: Box::new(ASTNode::Grouping(Box::new(binary(2.0, Token::Minus, 2.0)))),
+            span: Span::new(0, 11),
         };
-        let result = evaluator.evaluate_and_print(ast);
-        match result {
-            Ok(_) => print!("Didn't panic!"),
-            Err(e) => panic!("{}", e),
+        let error = evaluator.evaluate(ast).unwrap_err();
+        assert_eq!(error.kind, EvalErrorKind::DivisionByZero);
+        assert_eq!(error.span, Span::new(0, 11));
+        assert_eq!(error.step, 2);
+        assert_eq!(
+            error.to_string(),
+            "at step 2, division by zero in `5 / (2 - 2)`"
+        );
+
+        // The failing division is shown as written, not as `10 / 0`.
+        let tokens = Lexer::new().tokenize("1 + 10 / (4 - 2 * 2)").unwrap();
+        let ast = Parser::new(tokens).parse_expression().unwrap();
+        let error = evaluator.evaluate(ast).unwrap_err();
+        assert_eq!(error.step, 3);
+        assert_eq!(error.expression, "10 / (4 - 2 * 2)");
+        assert_eq!(error.span, Span::new(4, 20));
+    }
+
+    #[test]
+    fn test_domain_errors() {
+        let evaluator = Evaluator::new();
+
+        let ast = call("sqrt", vec![ASTNode::Number(-4.0)]);
+        assert_eq!(
+            evaluator.evaluate(ast).unwrap_err().kind,
+            EvalErrorKind::DomainError {
+                function: "sqrt",
+                value: -4.0
+            }
+        );
+
+        // The pole of tg is reported with the angle as it was written.
+        let ast = call("tg", vec![ASTNode::Number(90.0)]);
+        assert_eq!(
+            evaluator.evaluate(ast).unwrap_err().kind,
+            EvalErrorKind::DomainError {
+                function: "tg",
+                value: 90.0
+            }
+        );
+
+        let ast = ASTNode::UnaryOp {
+            op: Token::Fact,
+            operand: Box::new(ASTNode::Number(2.5)),
+            span: Span::default(),
+        };
+        assert_eq!(
+            evaluator.evaluate(ast).unwrap_err().kind,
+            EvalErrorKind::NotAnInteger {
+                function: "factorial",
+                value: 2.5
+            }
+        );
+    }
+
+    #[test]
+    fn test_implicit_multiplication_is_shown() {
+        let evaluator = Evaluator::new();
+
+        // 1/2pi is read as 1 / (2 * pi)
+        let ast = ASTNode::BinaryOp {
+            left: Box::new(ASTNode::Number(1.0)),
+            op: Token::Divide,
+            right: Box::new(ASTNode::BinaryOp {
+                left: Box::new(ASTNode::Number(2.0)),
+                op: Token::Multiply,
+                right: Box::new(ASTNode::Pi),
+                span: Span::default(),
+            }),
+            span: Span::default(),
+        };
+        let trace = evaluator.evaluate(ast).unwrap();
+        assert_eq!(trace.lines()[0], "= 1 / (2 * π)");
+    }
+
+    #[test]
+    fn test_exponent_and_unary_minus() {
+        let evaluator = Evaluator::new();
+
+        // 2 ^ 3 ^ 2 = 2 ^ 9
+        let ast = ASTNode::BinaryOp {
+            left: Box::new(ASTNode::Number(2.0)),
+            op: Token::Exponent,
+            right: Box::new(ASTNode::BinaryOp {
+                left: Box::new(ASTNode::Number(3.0)),
+                op: Token::Exponent,
+                right: Box::new(ASTNode::Number(2.0)),
+                span: Span::default(),
+            }),
+            span: Span::default(),
+        };
+        assert_eq!(evaluator.evaluate(ast).unwrap().result, 512.0);
+
+        // -2 ^ 2 = -(2 ^ 2)
+        let ast = ASTNode::UnaryOp {
+            op: Token::Minus,
+            operand: Box::new(ASTNode::BinaryOp {
+                left: Box::new(ASTNode::Number(2.0)),
+                op: Token::Exponent,
+                right: Box::new(ASTNode::Number(2.0)),
+                span: Span::default(),
+            }),
+            span: Span::default(),
+        };
+        assert_eq!(evaluator.evaluate(ast).unwrap().result, -4.0);
+    }
+
+    #[test]
+    fn test_precision_only_affects_display() {
+        let pi_times_1000 = || ASTNode::BinaryOp {
+            left: Box::new(ASTNode::Pi),
+            op: Token::Multiply,
+            right: Box::new(ASTNode::Number(1000.0)),
+            span: Span::default(),
+        };
+
+        let evaluator = Evaluator::new();
+        let trace = evaluator.evaluate(pi_times_1000()).unwrap();
+        assert_eq!(trace.result, PI * 1000.0);
+        assert_eq!(
+            trace.lines(),
+            vec!["= π * 1000", "= 3.14 * 1000", "= 3141.59"]
+        );
+
+        let settings = Settings {
+            school_rounding: true,
+            ..Settings::default()
+        };
+        let evaluator = Evaluator::with_settings(settings);
+        assert_eq!(evaluator.evaluate(pi_times_1000()).unwrap().result, 3140.0);
+    }
+
+    #[test]
+    fn test_angle_modes() {
+        let sin_of_asin = || call("sin", vec![call("asin", vec![ASTNode::Number(0.5)])]);
+        for angle_mode in [AngleMode::Degrees, AngleMode::Radians, AngleMode::Gradians] {
+            let settings = Settings {
+                angle_mode,
+                ..Settings::default()
+            };
+            let evaluator = Evaluator::with_settings(settings);
+            let result = evaluator.evaluate(sin_of_asin()).unwrap().result;
+            assert!((result - 0.5).abs() < 1e-12);
         }
     }
+
+    #[test]
+    fn test_angle_suffix_overrides_mode() {
+        let settings = Settings {
+            angle_mode: AngleMode::Radians,
+            ..Settings::default()
+        };
+        let evaluator = Evaluator::with_settings(settings);
+
+        let ast = call(
+            "sin",
+            vec![ASTNode::Angle {
+                value: Box::new(ASTNode::Number(30.0)),
+                unit: AngleMode::Degrees,
+                span: Span::default(),
+            }],
+        );
+        let trace = evaluator.evaluate(ast).unwrap();
+        assert!((trace.result - 0.5).abs() < 1e-12);
+        assert_eq!(trace.lines(), vec!["= sin(30°)", "= sin(0.52)", "= 0.5"]);
+    }
+
+    #[test]
+    fn test_degree_arguments_are_marked() {
+        let evaluator = Evaluator::new();
+
+        let ast = call("cos", vec![ASTNode::Number(60.0)]);
+        let trace = evaluator.evaluate(ast).unwrap();
+        assert_eq!(trace.lines(), vec!["= cos(60°)", "= 0.5"]);
+    }
+
+    #[test]
+    fn test_exact_fractions() {
+        let settings = Settings {
+            exact: true,
+            ..Settings::default()
+        };
+        let evaluator = Evaluator::with_settings(settings);
+
+        // 1/3 + 1/6
+        let ast = ASTNode::BinaryOp {
+            left: Box::new(ASTNode::BinaryOp {
+                left: Box::new(ASTNode::Number(1.0)),
+                op: Token::Divide,
+                right: Box::new(ASTNode::Number(3.0)),
+                span: Span::default(),
+            }),
+            op: Token::Plus,
+            right: Box::new(ASTNode::BinaryOp {
+                left: Box::new(ASTNode::Number(1.0)),
+                op: Token::Divide,
+                right: Box::new(ASTNode::Number(6.0)),
+                span: Span::default(),
+            }),
+            span: Span::default(),
+        };
+        let trace = evaluator.evaluate(ast).unwrap();
+        assert_eq!(trace.result, 0.5);
+        assert_eq!(
+            trace.lines(),
+            vec!["= 1/3 + 1/6", "= 2/6 + 1/6", "= 3/6", "= 1/2"]
+        );
+    }
+
+    #[test]
+    fn test_exact_decimals_and_powers() {
+        let settings = Settings {
+            exact: true,
+            ..Settings::default()
+        };
+
+        // 0.1 + 0.2
+        let evaluator = Evaluator::with_settings(settings.clone());
+        let ast = ASTNode::BinaryOp {
+            left: Box::new(ASTNode::Number(0.1)),
+            op: Token::Plus,
+            right: Box::new(ASTNode::Number(0.2)),
+            span: Span::default(),
+        };
+        let trace = evaluator.evaluate(ast).unwrap();
+        assert_eq!(trace.lines().last().unwrap(), "= 3/10");
+
+        // (2/3) ^ 2
+        let evaluator = Evaluator::with_settings(settings);
+        let ast = ASTNode::BinaryOp {
+            left: Box::new(ASTNode::Grouping(Box::new(ASTNode::BinaryOp {
+                left: Box::new(ASTNode::Number(2.0)),
+                op: Token::Divide,
+                right: Box::new(ASTNode::Number(3.0)),
+                span: Span::default(),
+            }))),
+            op: Token::Exponent,
+            right: Box::new(ASTNode::Number(2.0)),
+            span: Span::default(),
+        };
+        let trace = evaluator.evaluate(ast).unwrap();
+        assert_eq!(trace.lines(), vec!["= (2/3) ^ 2", "= 4/9"]);
+
+        let trace = evaluator
+            .evaluate(binary(1e300, Token::Divide, 3.0))
+            .unwrap();
+        assert_eq!(trace.lines(), vec!["= 1e300 / 3", "= 3.33e299"]);
+    }
+
+    #[test]
+    fn test_big_integer_results() {
+        let evaluator = Evaluator::new();
+
+        let ast = ASTNode::UnaryOp {
+            op: Token::Fact,
+            operand: Box::new(ASTNode::Number(25.0)),
+            span: Span::default(),
+        };
+        let trace = evaluator.evaluate(ast).unwrap();
+        assert_eq!(trace.lines(), vec!["= 25!", "= 15511210043330985984000000"]);
+
+        let evaluator = Evaluator::new();
+        let ast = ASTNode::BinaryOp {
+            left: Box::new(ASTNode::BinaryOp {
+                left: Box::new(ASTNode::Number(2.0)),
+                op: Token::Exponent,
+                right: Box::new(ASTNode::Number(64.0)),
+                span: Span::default(),
+            }),
+            op: Token::Minus,
+            right: Box::new(ASTNode::Number(1.0)),
+            span: Span::default(),
+        };
+        let trace = evaluator.evaluate(ast).unwrap();
+        assert_eq!(
+            trace.lines(),
+            vec![
+                "= 2 ^ 64 - 1",
+                "= 18446744073709551616 - 1",
+                "= 18446744073709551615"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_digit_limit() {
+        let evaluator = Evaluator::new();
+
+        let ast = ASTNode::UnaryOp {
+            op: Token::Fact,
+            operand: Box::new(ASTNode::Number(1e9)),
+            span: Span::default(),
+        };
+        let error = evaluator.evaluate(ast).unwrap_err();
+        assert!(matches!(
+            error.kind,
+            EvalErrorKind::TooManyDigits { limit: 1000, .. }
+        ));
+
+        let settings = Settings {
+            max_digits: 10,
+            ..Settings::default()
+        };
+        let evaluator = Evaluator::with_settings(settings);
+        let ast = ASTNode::BinaryOp {
+            left: Box::new(ASTNode::Number(10.0)),
+            op: Token::Exponent,
+            right: Box::new(ASTNode::Number(20.0)),
+            span: Span::default(),
+        };
+        assert!(evaluator.evaluate(ast).is_err());
+    }
+
+    fn binary(left: f64, op: Token, right: f64) -> ASTNode {
+        ASTNode::BinaryOp {
+            left: Box::new(ASTNode::Number(left)),
+            op,
+            right: Box::new(ASTNode::Number(right)),
+            span: Span::default(),
+        }
+    }
+
+    fn call(name: &str, args: Vec<ASTNode>) -> ASTNode {
+        ASTNode::Call {
+            name: name.to_string(),
+            args,
+            span: Span::default(),
+        }
+    }
+
+    #[test]
+    fn test_strict_steps_apply_one_operation() {
+        let evaluator = Evaluator::new();
+
+        // log(1 + 1, 4 * 4): the base is reduced before the number
+        let ast = call(
+            "log",
+            vec![
+                binary(1.0, Token::Plus, 1.0),
+                binary(4.0, Token::Multiply, 4.0),
+            ],
+        );
+        let trace = evaluator.evaluate(ast).unwrap();
+        assert_eq!(trace.result, 4.0);
+        assert_eq!(
+            trace.lines(),
+            vec!["= log(1 + 1,4 * 4)", "= log(2,4 * 4)", "= log(2,16)", "= 4"]
+        );
+    }
+
+    #[test]
+    fn test_level_steps_reduce_siblings_together() {
+        let settings = Settings {
+            reduction: Reduction::Level,
+            ..Settings::default()
+        };
+        let evaluator = Evaluator::with_settings(settings);
+
+        // (1 + 2) * (3 + 4) - 5 * 6
+        let ast = ASTNode::BinaryOp {
+            left: Box::new(ASTNode::BinaryOp {
+                left: Box::new(ASTNode::Grouping(Box::new(binary(1.0, Token::Plus, 2.0)))),
+                op: Token::Multiply,
+                right: Box::new(ASTNode::Grouping(Box::new(binary(3.0, Token::Plus, 4.0)))),
+                span: Span::default(),
+            }),
+            op: Token::Minus,
+            right: Box::new(binary(5.0, Token::Multiply, 6.0)),
+            span: Span::default(),
+        };
+        let trace = evaluator.evaluate(ast).unwrap();
+        assert_eq!(trace.result, -9.0);
+        assert_eq!(
+            trace.lines(),
+            vec![
+                "= (1 + 2) * (3 + 4) - 5 * 6",
+                "= 3 * 7 - 30",
+                "= 21 - 30",
+                "= -9"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_steps_record_operations() {
+        let evaluator = Evaluator::new();
+
+        // 2 + 3 * 16
+        let ast = ASTNode::BinaryOp {
+            left: Box::new(ASTNode::Number(2.0)),
+            op: Token::Plus,
+            right: Box::new(binary(3.0, Token::Multiply, 16.0)),
+            span: Span::default(),
+        };
+        let trace = evaluator.evaluate(ast).unwrap();
+        let steps = trace.steps;
+        assert_eq!(steps[0].to_string(), "multiplication → 2 + 48");
+        assert_eq!(
+            steps[0].operations,
+            vec![Operation {
+                rule: Rule::Multiplication,
+                description: "multiply 3 × 16".to_string(),
+                path: vec![1],
+            }]
+        );
+        assert_eq!(steps[1].to_string(), "addition → 50");
+
+        let settings = Settings {
+            school_rounding: true,
+            ..Settings::default()
+        };
+        let evaluator = Evaluator::with_settings(settings);
+        let trace = evaluator.evaluate(ASTNode::Pi).unwrap();
+        let steps = trace.steps;
+        assert_eq!(steps[0].operations[0].description, "replace π with 3.14");
+        assert_eq!(
+            steps[0].warnings,
+            vec!["3.141592653589793 was rounded to 3.14"]
+        );
+    }
+
+    #[test]
+    fn test_non_finite_results_are_errors() {
+        let evaluator = Evaluator::new();
+
+        let error = evaluator
+            .evaluate(binary(10.0, Token::Exponent, 400.5))
+            .unwrap_err();
+        assert_eq!(
+            error.kind,
+            EvalErrorKind::NotFinite {
+                operation: Rule::Power
+            }
+        );
+        assert!(evaluator
+            .evaluate(binary(-8.0, Token::Exponent, 0.5))
+            .is_err());
+    }
+
+    #[test]
+    fn test_real_roots_of_negative_bases() {
+        let evaluator = Evaluator::new();
+
+        let trace = evaluator
+            .evaluate(binary(-8.0, Token::Exponent, 1.0 / 3.0))
+            .unwrap();
+        assert!((trace.result + 2.0).abs() < 1e-12);
+        assert_eq!(trace.steps[0].operations[0].rule, Rule::RealRoot);
+        assert_eq!(
+            trace.steps[0].operations[0].description,
+            "take the real root of -8: the exponent 1/3 has an odd denominator"
+        );
+
+        let trace = evaluator
+            .evaluate(binary(-8.0, Token::Exponent, 2.0 / 3.0))
+            .unwrap();
+        assert!((trace.result - 4.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_factorial_expansions() {
+        let evaluator = Evaluator::new();
+        let factorial = |op: Token, n: f64| ASTNode::UnaryOp {
+            op,
+            operand: Box::new(ASTNode::Number(n)),
+            span: Span::default(),
+        };
+
+        let trace = evaluator.evaluate(factorial(Token::Fact, 5.0)).unwrap();
+        assert_eq!(trace.result, 120.0);
+        assert_eq!(trace.steps[0].operations[0].description, "5! = 5·4·3·2·1");
+
+        let trace = evaluator
+            .evaluate(factorial(Token::DoubleFact, 7.0))
+            .unwrap();
+        assert_eq!(trace.result, 105.0);
+        assert_eq!(trace.steps[0].operations[0].description, "7!! = 7·5·3·1");
+
+        let trace = evaluator.evaluate(factorial(Token::Subfact, 4.0)).unwrap();
+        assert_eq!(trace.result, 9.0);
+        assert_eq!(
+            trace.steps[0].operations[0].description,
+            "!4 = 4!·(1 - 1/1! + 1/2! - 1/3! + 1/4!)"
+        );
+    }
+
+    #[test]
+    fn test_gamma() {
+        let evaluator = Evaluator::new();
+        let gamma = |x: f64| call("gamma", vec![ASTNode::Number(x)]);
+        let result = evaluator.evaluate(gamma(5.0)).unwrap().result;
+        assert!((result - 24.0).abs() < 1e-10);
+        let result = evaluator.evaluate(gamma(-0.5)).unwrap().result;
+        assert!((result + 2.0 * PI.sqrt()).abs() < 1e-10);
+        assert!(evaluator.evaluate(gamma(-2.0)).is_err());
+
+        // 0.5! is rejected unless real factorials are enabled
+        let half_factorial = || ASTNode::UnaryOp {
+            op: Token::Fact,
+            operand: Box::new(ASTNode::Number(0.5)),
+            span: Span::default(),
+        };
+        assert!(evaluator.evaluate(half_factorial()).is_err());
+        let settings = Settings {
+            gamma_factorial: true,
+            ..Settings::default()
+        };
+        let evaluator = Evaluator::with_settings(settings);
+        let trace = evaluator.evaluate(half_factorial()).unwrap();
+        assert!((trace.result - PI.sqrt() / 2.0).abs() < 1e-10);
+        assert_eq!(trace.steps[0].operations[0].description, "0.5! = Γ(1.5)");
+    }
+
+    #[test]
+    fn test_variable_substitution() {
+        let variable = |name: &str| ASTNode::Variable {
+            name: name.to_string(),
+            span: Span::default(),
+        };
+        let mut environment = Environment::new();
+        environment.assign("x", ASTNode::Number(5.0));
+        environment.push_result(ASTNode::Number(3.0));
+        let evaluator = Evaluator::with_environment(Settings::default(), environment);
+
+        let trace = evaluator
+            .evaluate(ASTNode::BinaryOp {
+                left: Box::new(variable("x")),
+                op: Token::Multiply,
+                right: Box::new(variable("ans")),
+                span: Span::default(),
+            })
+            .unwrap();
+        assert_eq!(trace.result, 15.0);
+        assert_eq!(
+            trace.lines(),
+            vec!["= x * ans", "= 5 * ans", "= 5 * 3", "= 15"]
+        );
+        assert_eq!(trace.steps[0].operations[0].rule, Rule::Substitution);
+        assert_eq!(trace.steps[0].operations[0].description, "substitute x = 5");
+
+        let err = evaluator.evaluate(variable("y")).unwrap_err();
+        assert_eq!(
+            err.kind,
+            EvalErrorKind::UndefinedVariable {
+                name: "y".to_string()
+            }
+        );
+        assert_eq!(err.to_string(), "at step 1, `y` is not defined in `y`");
+    }
+
+    #[test]
+    fn test_user_function_calls() {
+        let variable = |name: &str| ASTNode::Variable {
+            name: name.to_string(),
+            span: Span::default(),
+        };
+        let mut environment = Environment::new();
+        environment.define(
+            "f",
+            UserFunction {
+                parameters: vec!["x".to_string(), "r".to_string()],
+                body: ASTNode::BinaryOp {
+                    left: Box::new(variable("x")),
+                    op: Token::Multiply,
+                    right: Box::new(ASTNode::BinaryOp {
+                        left: Box::new(ASTNode::Number(1.0)),
+                        op: Token::Plus,
+                        right: Box::new(variable("r")),
+                        span: Span::default(),
+                    }),
+                    span: Span::default(),
+                },
+            },
+        );
+        environment.define(
+            "g",
+            UserFunction {
+                parameters: vec!["x".to_string()],
+                body: call("g", vec![variable("x")]),
+            },
+        );
+        let evaluator = Evaluator::with_environment(Settings::default(), environment);
+
+        let trace = evaluator
+            .evaluate(call(
+                "f",
+                vec![binary(500.0, Token::Multiply, 2.0), ASTNode::Number(0.5)],
+            ))
+            .unwrap();
+        assert_eq!(trace.result, 1500.0);
+        assert_eq!(
+            trace.lines(),
+            vec![
+                "= f(500 * 2, 0.5)",
+                "= f(1000, 0.5)",
+                "= 1000 * (1 + 0.5)",
+                "= 1000 * 1.5",
+                "= 1500"
+            ]
+        );
+        assert_eq!(
+            trace.steps[1].operations[0].description,
+            "substitute x = 1000, r = 0.5 into f"
+        );
+
+        let err = evaluator
+            .evaluate(call("g", vec![ASTNode::Number(1.0)]))
+            .unwrap_err();
+        assert_eq!(
+            err.kind,
+            EvalErrorKind::RecursionLimit {
+                name: "g".to_string(),
+                limit: MAX_CALLS
+            }
+        );
+
+        let err = evaluator
+            .evaluate(call("f", vec![ASTNode::Number(1.0)]))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "at step 1, `f` takes 2 arguments, got 1 in `f(1)`"
+        );
+    }
+
+    #[test]
+    fn test_registered_functions() {
+        let mut environment = Environment::new();
+        environment.builtins_mut().register(Builtin {
+            arity: Arity::Exactly(2),
+            domain: |args| (args[1] == 0.0).then_some(1),
+            display: |_, args| format!("{} over {}", args[0], args[1]),
+            ..Builtin::unary("ratio", |args| args[0] / args[1])
+        });
+        let evaluator = Evaluator::with_environment(Settings::default(), environment);
+
+        let trace = evaluator
+            .evaluate(call(
+                "ratio",
+                vec![binary(3.0, Token::Plus, 3.0), ASTNode::Number(4.0)],
+            ))
+            .unwrap();
+        assert_eq!(trace.lines(), vec!["= 3 + 3 over 4", "= 6 over 4", "= 1.5"]);
+
+        let err = evaluator
+            .evaluate(call(
+                "ratio",
+                vec![ASTNode::Number(1.0), ASTNode::Number(0.0)],
+            ))
+            .unwrap_err();
+        assert_eq!(
+            err.kind,
+            EvalErrorKind::DomainError {
+                function: "ratio",
+                value: 0.0
+            }
+        );
+    }
+
+    #[test]
+    fn test_multi_argument_descriptions() {
+        let evaluator = Evaluator::new();
+        let numbers = |values: &[f64]| values.iter().map(|&v| ASTNode::Number(v)).collect();
+
+        let trace = evaluator
+            .evaluate(call("min", numbers(&[3.0, 1.0, 2.0])))
+            .unwrap();
+        assert_eq!(trace.result, 1.0);
+        assert_eq!(
+            trace.steps[0].operations[0].description,
+            "take the smallest of 3, 1, 2"
+        );
+
+        let trace = evaluator
+            .evaluate(call("root", numbers(&[3.0, -8.0])))
+            .unwrap();
+        assert_eq!(trace.result, -2.0);
+        assert_eq!(
+            trace.steps[0].operations[0].description,
+            "take the 3rd root of -8"
+        );
+
+        let trace = evaluator
+            .evaluate(call("atan2", numbers(&[1.0, 1.0])))
+            .unwrap();
+        assert!((trace.result - 45.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_function_names_follow_the_setting() {
+        let tan = || call("tan", vec![ASTNode::Number(45.0)]);
+        let trace = Evaluator::new().evaluate(tan()).unwrap();
+        assert_eq!(trace.lines(), vec!["= tg(45°)", "= 1"]);
+
+        let settings = Settings {
+            function_names: FunctionNames::Anglophone,
+            ..Settings::default()
+        };
+        let evaluator = Evaluator::with_settings(settings);
+        let trace = evaluator
+            .evaluate(call("tg", vec![ASTNode::Number(45.0)]))
+            .unwrap();
+        assert_eq!(trace.lines(), vec!["= tan(45°)", "= 1"]);
+    }
+
+    #[test]
+    fn test_output_modes() {
+        let settings = Settings {
+            output: Output::Math,
+            ..Settings::default()
+        };
+        let evaluator = Evaluator::with_settings(settings);
+
+        // sqrt(2 * 8) / pi + 3 ^ 2
+        let ast = ASTNode::BinaryOp {
+            left: Box::new(ASTNode::BinaryOp {
+                left: Box::new(call("sqrt", vec![binary(2.0, Token::Multiply, 8.0)])),
+                op: Token::Divide,
+                right: Box::new(ASTNode::Pi),
+                span: Span::default(),
+            }),
+            op: Token::Plus,
+            right: Box::new(binary(3.0, Token::Exponent, 2.0)),
+            span: Span::default(),
+        };
+        let trace = evaluator.evaluate(ast).unwrap();
+        assert_eq!(trace.lines()[0], "= √(2 × 8) ÷ π + 3²");
+        assert_eq!(trace.steps[0].operations[0].description, "multiply 2 × 8");
+
+        let trace = evaluator
+            .evaluate(binary(10.0, Token::Exponent, -3.0))
+            .unwrap();
+        assert_eq!(trace.lines()[0], "= 10⁻³");
+        let trace = evaluator
+            .evaluate(call("cos", vec![ASTNode::Number(60.0)]))
+            .unwrap();
+        assert_eq!(trace.lines()[0], "= cos(60°)");
+
+        let ascii = Evaluator::with_settings(Settings {
+            output: Output::Ascii,
+            ..Settings::default()
+        });
+        let ast = ASTNode::BinaryOp {
+            left: Box::new(call("cos", vec![ASTNode::Number(60.0)])),
+            op: Token::Multiply,
+            right: Box::new(ASTNode::Pi),
+            span: Span::default(),
+        };
+        let trace = ascii.evaluate(ast).unwrap();
+        assert_eq!(trace.lines()[0], "= cos(60 deg) * pi");
+        assert_eq!(
+            trace.steps[1].operations[0].description,
+            "replace pi with 3.14"
+        );
+    }
+
+    #[test]
+    fn test_programmer_mode() {
+        let programmer = |bits, signed, base| {
+            Evaluator::with_settings(Settings {
+                programmer: Some(Word { bits, signed }),
+                base,
+                ..Settings::default()
+            })
+        };
+        let u8 = programmer(8, false, Base::Decimal);
+
+        let trace = u8.evaluate(binary(200.0, Token::Plus, 100.0)).unwrap();
+        assert_eq!(trace.result, 44.0);
+        assert_eq!(
+            trace.steps[0].operations[0].description,
+            "add 200 + 100: 44 = 0x2c = 0o54 = 0b101100"
+        );
+        assert_eq!(
+            trace.steps[0].warnings,
+            vec!["200 + 100 overflows u8 and wraps to 44"]
+        );
+        let invert = ASTNode::UnaryOp {
+            op: Token::BitNot,
+            operand: Box::new(ASTNode::Number(0.0)),
+            span: Span::default(),
+        };
+        assert_eq!(u8.evaluate(invert).unwrap().result, 255.0);
+
+        // 0xff is -1 as an i8; hex shows the bits.
+        let i8 = programmer(8, true, Base::Hexadecimal);
+        let ast = ASTNode::BinaryOp {
+            left: Box::new(ASTNode::Integer(BigInt::from_i128(255))),
+            op: Token::BitAnd,
+            right: Box::new(ASTNode::Number(15.0)),
+            span: Span::default(),
+        };
+        let trace = i8.evaluate(ast).unwrap();
+        assert_eq!(trace.lines(), vec!["= 0xff & 0xf", "= 0xf"]);
+
+        let i8 = programmer(8, true, Base::Decimal);
+        // -128 / -1 overflows; the literal -128 itself does not.
+        let negate = |value: f64| ASTNode::UnaryOp {
+            op: Token::Minus,
+            operand: Box::new(ASTNode::Number(value)),
+            span: Span::default(),
+        };
+        let ast = ASTNode::BinaryOp {
+            left: Box::new(negate(128.0)),
+            op: Token::Divide,
+            right: Box::new(negate(1.0)),
+            span: Span::default(),
+        };
+        let trace = i8.evaluate(ast).unwrap();
+        assert_eq!(trace.lines(), vec!["= -128 / -1", "= -128"]);
+        assert_eq!(
+            trace.steps[0].warnings,
+            vec!["-128 / -1 overflows i8 and wraps to -128"]
+        );
+        let err = i8.evaluate(ASTNode::Number(128.0)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "at step 1, 128 does not fit in i8 in `128`"
+        );
+        assert_eq!(
+            Evaluator::new().evaluate(negate(-5.0)).unwrap().lines()[0],
+            "= -(-5)"
+        );
+
+        let trace = i8.evaluate(binary(-7.0, Token::ShiftRight, 1.0)).unwrap();
+        assert_eq!(trace.result, -4.0);
+        let trace = i8.evaluate(binary(-7.0, Token::Divide, 2.0)).unwrap();
+        assert_eq!(trace.result, -3.0);
+        assert!(trace.steps[0].operations[0]
+            .description
+            .starts_with("divide -7 ÷ 2, dropping the remainder"));
+        let trace = i8.evaluate(binary(3.0, Token::Exponent, 5.0)).unwrap();
+        assert_eq!(trace.result, -13.0);
+
+        let u64 = programmer(64, false, Base::Decimal);
+        let trace = u64.evaluate(binary(2.0, Token::Exponent, 64.0)).unwrap();
+        assert_eq!(trace.result, 0.0);
+        assert_eq!(trace.steps[0].warnings.len(), 1);
+
+        let err = i8.evaluate(binary(1.0, Token::ShiftLeft, 8.0)).unwrap_err();
+        assert_eq!(
+            err.kind,
+            EvalErrorKind::ShiftOutOfRange { amount: 8, bits: 8 }
+        );
+        let err = i8.evaluate(binary(2.5, Token::Plus, 1.0)).unwrap_err();
+        assert_eq!(
+            err.kind,
+            EvalErrorKind::NotAnInteger {
+                function: "programmer mode",
+                value: 2.5
+            }
+        );
+        let err = i8
+            .evaluate(call("sqrt", vec![ASTNode::Number(2.0)]))
+            .unwrap_err();
+        assert!(matches!(err.kind, EvalErrorKind::NotAnInteger { .. }));
+        assert_eq!(
+            i8.evaluate(call("sqrt", vec![ASTNode::Number(16.0)]))
+                .unwrap()
+                .result,
+            4.0
+        );
+
+        let err = Evaluator::new()
+            .evaluate(binary(6.0, Token::BitXor, 3.0))
+            .unwrap_err();
+        assert_eq!(err.kind, EvalErrorKind::ProgrammerOnly { operator: "xor" });
+    }
+
+    #[test]
+    fn test_remainder_and_integer_division() {
+        let evaluator = Evaluator::new();
+        let trace = evaluator.evaluate(binary(7.0, Token::Modulo, 3.0)).unwrap();
+        assert_eq!(trace.result, 1.0);
+        assert_eq!(
+            trace.steps[0].operations[0].description,
+            "7 mod 3 = 1, since 7 = 2 * 3 + 1"
+        );
+        // The remainder takes the sign of the divisor.
+        assert_eq!(
+            evaluator
+                .evaluate(binary(-7.0, Token::Modulo, 3.0))
+                .unwrap()
+                .result,
+            2.0
+        );
+        assert_eq!(
+            evaluator
+                .evaluate(binary(7.0, Token::Modulo, -3.0))
+                .unwrap()
+                .result,
+            -2.0
+        );
+        assert_eq!(
+            evaluator
+                .evaluate(binary(7.5, Token::Modulo, 2.0))
+                .unwrap()
+                .result,
+            1.5
+        );
+
+        let trace = evaluator
+            .evaluate(binary(-7.0, Token::IntDivide, 2.0))
+            .unwrap();
+        assert_eq!(trace.result, -4.0);
+        assert_eq!(
+            trace.steps[0].operations[0].description,
+            "-7 // 2 = -4, since -7 = -4 * 2 + 1"
+        );
+        let err = evaluator
+            .evaluate(binary(1.0, Token::Modulo, 0.0))
+            .unwrap_err();
+        assert_eq!(err.kind, EvalErrorKind::DivisionByZero);
+
+        let exact = Evaluator::with_settings(Settings {
+            exact: true,
+            ..Settings::default()
+        });
+        let ast = ASTNode::BinaryOp {
+            left: Box::new(ASTNode::Fraction(Rational::new(7, 2).unwrap())),
+            op: Token::Modulo,
+            right: Box::new(ASTNode::Number(1.0)),
+            span: Span::default(),
+        };
+        assert_eq!(exact.evaluate(ast).unwrap().lines().pop().unwrap(), "= 1/2");
+
+        let i8 = Evaluator::with_settings(Settings {
+            programmer: Some(Word {
+                bits: 8,
+                signed: true,
+            }),
+            ..Settings::default()
+        });
+        assert_eq!(
+            i8.evaluate(binary(-7.0, Token::Modulo, 3.0))
+                .unwrap()
+                .result,
+            2.0
+        );
+        assert_eq!(
+            i8.evaluate(binary(-7.0, Token::IntDivide, 2.0))
+                .unwrap()
+                .result,
+            -4.0
+        );
+    }
+
+    #[test]
+    fn test_percent() {
+        let percent = |value: f64| ASTNode::UnaryOp {
+            op: Token::Percent,
+            operand: Box::new(ASTNode::Number(value)),
+            span: Span::default(),
+        };
+        let evaluator = Evaluator::new();
+
+        let ast = ASTNode::BinaryOp {
+            left: Box::new(ASTNode::Number(200.0)),
+            op: Token::Plus,
+            right: Box::new(percent(10.0)),
+            span: Span::default(),
+        };
+        let trace = evaluator.evaluate(ast).unwrap();
+        assert_eq!(trace.lines(), vec!["= 200 + 10%", "= 200 + 20", "= 220"]);
+        assert_eq!(trace.steps[0].operations[0].rule, Rule::Percent);
+        assert_eq!(trace.steps[0].operations[0].description, "10% of 200 = 20");
+        assert_eq!(trace.steps[0].operations[0].path, vec![1]);
+
+        // The whole the percent is taken of is reduced first.
+        let ast = ASTNode::BinaryOp {
+            left: Box::new(binary(100.0, Token::Multiply, 2.0)),
+            op: Token::Minus,
+            right: Box::new(percent(10.0)),
+            span: Span::default(),
+        };
+        let trace = evaluator.evaluate(ast).unwrap();
+        assert_eq!(
+            trace.lines(),
+            vec!["= 100 * 2 - 10%", "= 200 - 10%", "= 200 - 20", "= 180"]
+        );
+
+        let ast = ASTNode::BinaryOp {
+            left: Box::new(ASTNode::Number(50.0)),
+            op: Token::Multiply,
+            right: Box::new(percent(20.0)),
+            span: Span::default(),
+        };
+        let trace = evaluator.evaluate(ast).unwrap();
+        assert_eq!(trace.lines(), vec!["= 50 * 20%", "= 50 * 0.2", "= 10"]);
+        assert_eq!(trace.steps[0].operations[0].description, "20% = 20 / 100");
+    }
+
+    #[test]
+    fn test_comparisons_and_logic() {
+        let evaluator = Evaluator::new();
+        let compare = |left: ASTNode, op: Token, right: ASTNode| ASTNode::BinaryOp {
+            left: Box::new(left),
+            op,
+            right: Box::new(right),
+            span: Span::default(),
+        };
+
+        // sin(30)^2 + cos(30)^2 == 1, with each side reduced first
+        let square = |name: &str| {
+            compare(
+                call(name, vec![ASTNode::Number(30.0)]),
+                Token::Exponent,
+                ASTNode::Number(2.0),
+            )
+        };
+        let identity = compare(
+            compare(square("sin"), Token::Plus, square("cos")),
+            Token::Equal,
+            ASTNode::Number(1.0),
+        );
+        let trace = evaluator.evaluate(identity).unwrap();
+        assert_eq!(trace.value, ASTNode::Boolean(true));
+        assert_eq!(trace.result, 1.0);
+        let lines = trace.lines();
+        assert_eq!(lines[lines.len() - 2..], ["= 1 == 1", "= true"]);
+        let last = &trace.steps.last().unwrap().operations[0];
+        assert_eq!(last.rule, Rule::Comparison);
+
+        // 0.1 + 0.2 is off by one float rounding.
+        let sum = || binary(0.1, Token::Plus, 0.2);
+        let trace = evaluator
+            .evaluate(compare(sum(), Token::Equal, ASTNode::Number(0.3)))
+            .unwrap();
+        assert_eq!(trace.value, ASTNode::Boolean(true));
+        assert_eq!(
+            trace.steps[1].operations[0].description,
+            "0.3 == 0.3 is true (they differ by 5.6e-17, within the tolerance 1e-9)"
+        );
+        let strict = Evaluator::with_settings(Settings {
+            tolerance: 0.0,
+            ..Settings::default()
+        });
+        let trace = strict
+            .evaluate(compare(sum(), Token::LessEqual, ASTNode::Number(0.3)))
+            .unwrap();
+        assert_eq!(trace.value, ASTNode::Boolean(false));
+
+        // false and 1 / 0 == 1 never divides.
+        let ast = compare(
+            ASTNode::Boolean(false),
+            Token::And,
+            compare(
+                binary(1.0, Token::Divide, 0.0),
+                Token::Equal,
+                ASTNode::Number(1.0),
+            ),
+        );
+        let trace = evaluator.evaluate(ast).unwrap();
+        assert_eq!(trace.lines(), vec!["= false and 1 / 0 == 1", "= false"]);
+        assert_eq!(trace.steps[0].operations[0].rule, Rule::Logic);
+
+        let not = ASTNode::UnaryOp {
+            op: Token::Not,
+            operand: Box::new(binary(3.0, Token::GreaterEqual, 4.0)),
+            span: Span::default(),
+        };
+        let trace = evaluator.evaluate(not).unwrap();
+        assert_eq!(trace.lines(), vec!["= not 3 >= 4", "= not false", "= true"]);
+
+        let err = evaluator
+            .evaluate(compare(
+                ASTNode::Boolean(true),
+                Token::Plus,
+                ASTNode::Number(1.0),
+            ))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "at step 1, `+` needs a number, got true in `true + 1`"
+        );
+        let err = evaluator
+            .evaluate(compare(
+                ASTNode::Number(1.0),
+                Token::Or,
+                ASTNode::Boolean(true),
+            ))
+            .unwrap_err();
+        assert_eq!(
+            err.kind,
+            EvalErrorKind::NotABoolean {
+                operation: "or",
+                value: 1.0
+            }
+        );
+    }
 }
@@ -0,0 +1,635 @@
+use crate::settings::FunctionNames;
+use crate::step::Rule;
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::fmt;
+
+/// Parameters of the Lanczos approximation of the gamma function, g = 7.
+const LANCZOS_G: f64 = 7.0;
+const LANCZOS_COEFFICIENTS: [f64; 9] = [
+    0.999_999_999_999_809_9,
+    676.520_368_121_885_1,
+    -1_259.139_216_722_402_8,
+    771.323_428_777_653_1,
+    -176.615_029_162_140_6,
+    12.507_343_278_686_905,
+    -0.138_571_095_265_720_12,
+    9.984_369_578_019_572e-6,
+    1.505_632_735_149_311_6e-7,
+];
+
+/// Below this, the sine or cosine of an angle is taken to be zero when
+/// checking for poles, e.g. `tg(90°)`.
+const POLE_TOLERANCE: f64 = 1e-10;
+
+/// Groups of the built-in functions in the help.
+const TRIGONOMETRIC: &str = "Trigonometric functions";
+const HYPERBOLIC: &str = "Hyperbolic functions";
+const EXPONENTIAL: &str = "Exponential and logarithmic functions";
+const OTHER: &str = "Other functions";
+
+/// How many arguments a function accepts.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Arity {
+    Exactly(usize),
+    Between(usize, usize),
+    AtLeast(usize),
+}
+
+impl Arity {
+    pub fn accepts(&self, count: usize) -> bool {
+        match *self {
+            Arity::Exactly(n) => count == n,
+            Arity::Between(min, max) => (min..=max).contains(&count),
+            Arity::AtLeast(min) => count >= min,
+        }
+    }
+}
+
+impl fmt::Display for Arity {
+    /// Writes e.g. `2 arguments`, `1 to 2 arguments` or `at least 1 argument`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let plural = |n: usize| if n == 1 { "" } else { "s" };
+        match *self {
+            Arity::Exactly(n) => write!(f, "{} argument{}", n, plural(n)),
+            Arity::Between(min, max) => write!(f, "{} to {} arguments", min, max),
+            Arity::AtLeast(min) => write!(f, "at least {} argument{}", min, plural(min)),
+        }
+    }
+}
+
+/// Which values of a function are angles, and so follow the angle mode.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Angles {
+    None,
+    /// The argument is an angle, e.g. `sin`; the implementation gets radians.
+    Argument,
+    /// The result is an angle, e.g. `asin`; the implementation returns
+    /// radians.
+    Result,
+}
+
+/// A built-in function of the calculator.
+///
+/// Start from [`Builtin::unary`] and override the fields that differ:
+///
+/// ```
+/// use my_calculator::functions::{Builtin, FunctionRegistry};
+///
+/// let mut registry = FunctionRegistry::default();
+/// registry.register(Builtin {
+///     aliases: &["cuberoot"],
+///     ..Builtin::unary("cbrt", |args| args[0].cbrt())
+/// });
+/// assert!(registry.get("cuberoot").is_some());
+/// ```
+#[derive(Debug, Clone)]
+pub struct Builtin {
+    /// The name, as shown with continental function names.
+    pub name: &'static str,
+    /// The name shown with anglophone function names, when it differs,
+    /// e.g. `tan` for `tg`. It is accepted as input too.
+    pub anglophone: Option<&'static str>,
+    /// Other spellings that call the same function.
+    pub aliases: &'static [&'static str],
+    pub arity: Arity,
+    /// The heading it is listed under in the help.
+    pub group: &'static str,
+    /// How the help shows it, e.g. `root(n, x)`; just the name if `None`.
+    pub usage: Option<&'static str>,
+    /// The rule recorded for the step that evaluates it.
+    pub rule: Rule,
+    pub angles: Angles,
+    /// The index of an argument outside the domain, if any. It is given the
+    /// same arguments as `implementation`.
+    pub domain: fn(&[f64]) -> Option<usize>,
+    pub implementation: fn(&[f64]) -> f64,
+    /// Shows a call from its name and its already formatted arguments.
+    pub display: fn(&str, &[String]) -> String,
+    /// Describes the step that evaluates a call from its formatted
+    /// arguments, e.g. `take the smallest of 3, 1, 2`. Without one the step
+    /// reads `evaluate min(3, 1, 2)`.
+    pub describe: Option<fn(&[String]) -> String>,
+    /// Written before the parenthesized arguments instead of the name in
+    /// math output, e.g. `√` for `sqrt`.
+    pub symbol: Option<&'static str>,
+}
+
+impl Builtin {
+    /// A function of one argument defined everywhere, shown as `name(x)`.
+    pub fn unary(name: &'static str, implementation: fn(&[f64]) -> f64) -> Self {
+        Self {
+            name,
+            anglophone: None,
+            aliases: &[],
+            arity: Arity::Exactly(1),
+            group: OTHER,
+            usage: None,
+            rule: Rule::Function,
+            angles: Angles::None,
+            domain: |_| None,
+            implementation,
+            display: call_notation,
+            describe: None,
+            symbol: None,
+        }
+    }
+}
+
+impl Builtin {
+    /// The name to show in the given style.
+    pub fn display_name(&self, names: FunctionNames) -> &'static str {
+        match (names, self.anglophone) {
+            (FunctionNames::Anglophone, Some(anglophone)) => anglophone,
+            _ => self.name,
+        }
+    }
+}
+
+/// Shows a call as `name(a, b)`.
+pub fn call_notation(name: &str, args: &[String]) -> String {
+    format!("{}({})", name, args.join(", "))
+}
+
+/// The built-in functions by name and alias.
+#[derive(Debug, Clone)]
+pub struct FunctionRegistry {
+    functions: Vec<Builtin>,
+    names: HashMap<&'static str, usize>,
+}
+
+impl Default for FunctionRegistry {
+    /// A registry with the calculator's built-in functions.
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        for builtin in builtins() {
+            registry.register(builtin);
+        }
+        registry
+    }
+}
+
+impl FunctionRegistry {
+    pub fn empty() -> Self {
+        Self {
+            functions: Vec::new(),
+            names: HashMap::new(),
+        }
+    }
+
+    /// Adds a function, replacing any with the same name or alias.
+    pub fn register(&mut self, builtin: Builtin) {
+        let index = self.functions.len();
+        let names = std::iter::once(builtin.name)
+            .chain(builtin.anglophone)
+            .chain(builtin.aliases.iter().copied());
+        for name in names {
+            self.names.insert(name, index);
+        }
+        self.functions.push(builtin);
+    }
+
+    /// Makes `alias` another spelling of the function called `name`.
+    /// Returns `false` when there is no such function.
+    pub fn add_alias(&mut self, alias: &'static str, name: &str) -> bool {
+        match self.names.get(name) {
+            Some(&index) => {
+                self.names.insert(alias, index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Builtin> {
+        self.names.get(name).map(|&index| &self.functions[index])
+    }
+
+    /// The functions still reachable by their name, in registration order.
+    pub fn iter(&self) -> impl Iterator<Item = &Builtin> {
+        self.functions
+            .iter()
+            .enumerate()
+            .filter(|(index, builtin)| self.names.get(builtin.name) == Some(index))
+            .map(|(_, builtin)| builtin)
+    }
+
+    /// One line per group for the help, e.g.
+    /// `Hyperbolic functions: sinh, cosh, tanh, ...`.
+    pub fn help(&self) -> Vec<String> {
+        let mut groups: Vec<(&str, Vec<&str>)> = Vec::new();
+        for builtin in self.iter() {
+            let usage = builtin.usage.unwrap_or(builtin.name);
+            match groups.iter_mut().find(|(group, _)| *group == builtin.group) {
+                Some((_, usages)) => usages.push(usage),
+                None => groups.push((builtin.group, vec![usage])),
+            }
+        }
+        groups
+            .into_iter()
+            .map(|(group, usages)| format!("{}: {}", group, usages.join(", ")))
+            .collect()
+    }
+
+    /// The arity of every name and alias, for the parser.
+    pub fn arities(&self) -> HashMap<String, Arity> {
+        self.names
+            .iter()
+            .map(|(name, &index)| (name.to_string(), self.functions[index].arity))
+            .collect()
+    }
+}
+
+fn builtins() -> Vec<Builtin> {
+    let negative = |args: &[f64]| (args[0] < 0.0).then_some(0);
+    let not_positive = |args: &[f64]| (args[0] <= 0.0).then_some(0);
+    let outside_unit = |args: &[f64]| (!(-1.0..=1.0).contains(&args[0])).then_some(0);
+    let cos_zero = |args: &[f64]| (args[0].cos().abs() < POLE_TOLERANCE).then_some(0);
+    let sin_zero = |args: &[f64]| (args[0].sin().abs() < POLE_TOLERANCE).then_some(0);
+    let trigonometric = |name, implementation| Builtin {
+        group: TRIGONOMETRIC,
+        angles: Angles::Argument,
+        ..Builtin::unary(name, implementation)
+    };
+    let inverse = |name, implementation| Builtin {
+        group: TRIGONOMETRIC,
+        angles: Angles::Result,
+        ..Builtin::unary(name, implementation)
+    };
+    let hyperbolic = |name, implementation| Builtin {
+        group: HYPERBOLIC,
+        ..Builtin::unary(name, implementation)
+    };
+    let exponential = |name, implementation| Builtin {
+        group: EXPONENTIAL,
+        ..Builtin::unary(name, implementation)
+    };
+    vec![
+        trigonometric("sin", |args| args[0].sin()),
+        trigonometric("cos", |args| args[0].cos()),
+        Builtin {
+            anglophone: Some("tan"),
+            domain: cos_zero,
+            ..trigonometric("tg", |args| args[0].tan())
+        },
+        Builtin {
+            anglophone: Some("cot"),
+            aliases: &["ctg"],
+            domain: sin_zero,
+            ..trigonometric("cotg", |args| 1.0 / args[0].tan())
+        },
+        Builtin {
+            domain: cos_zero,
+            ..trigonometric("sec", |args| 1.0 / args[0].cos())
+        },
+        Builtin {
+            aliases: &["cosec"],
+            domain: sin_zero,
+            ..trigonometric("csc", |args| 1.0 / args[0].sin())
+        },
+        Builtin {
+            aliases: &["arcsin"],
+            domain: outside_unit,
+            ..inverse("asin", |args| args[0].asin())
+        },
+        Builtin {
+            aliases: &["arccos"],
+            domain: outside_unit,
+            ..inverse("acos", |args| args[0].acos())
+        },
+        Builtin {
+            anglophone: Some("atan"),
+            aliases: &["arctg", "arctan"],
+            ..inverse("atg", |args| args[0].atan())
+        },
+        Builtin {
+            anglophone: Some("acot"),
+            aliases: &["arcctg", "arccot"],
+            domain: |args| (args[0] == 0.0).then_some(0),
+            ..inverse("actg", |args| PI / 2.0 - args[0].atan())
+        },
+        // atan2(y, x): the angle of the point (x, y), in the angle mode.
+        Builtin {
+            arity: Arity::Exactly(2),
+            usage: Some("atan2(y, x)"),
+            domain: |args| (args[0] == 0.0 && args[1] == 0.0).then_some(1),
+            describe: Some(|args| {
+                format!("find the angle of the point ({}, {})", args[1], args[0])
+            }),
+            ..inverse("atan2", |args| args[0].atan2(args[1]))
+        },
+        hyperbolic("sinh", |args| args[0].sinh()),
+        hyperbolic("cosh", |args| args[0].cosh()),
+        hyperbolic("tanh", |args| args[0].tanh()),
+        Builtin {
+            domain: |args| (args[0] == 0.0).then_some(0),
+            ..hyperbolic("coth", |args| 1.0 / args[0].tanh())
+        },
+        Builtin {
+            aliases: &["arsinh", "arcsinh"],
+            ..hyperbolic("asinh", |args| args[0].asinh())
+        },
+        Builtin {
+            aliases: &["arcosh", "arccosh"],
+            domain: |args| (args[0] < 1.0).then_some(0),
+            ..hyperbolic("acosh", |args| args[0].acosh())
+        },
+        Builtin {
+            aliases: &["artanh", "arctanh"],
+            domain: |args| (args[0].abs() >= 1.0).then_some(0),
+            ..hyperbolic("atanh", |args| args[0].atanh())
+        },
+        Builtin {
+            aliases: &["arcoth", "arccoth"],
+            domain: |args| (args[0].abs() <= 1.0).then_some(0),
+            ..hyperbolic("acoth", |args| (1.0 / args[0]).atanh())
+        },
+        exponential("exp", |args| args[0].exp()),
+        Builtin {
+            domain: not_positive,
+            ..exponential("ln", |args| args[0].ln())
+        },
+        // log(x) is the binary logarithm, log(b, x) the logarithm base b.
+        Builtin {
+            arity: Arity::Between(1, 2),
+            usage: Some("log(base, number)"),
+            rule: Rule::Logarithm,
+            domain: |args| match *args {
+                [x] => (x <= 0.0).then_some(0),
+                [b, x] => {
+                    if b <= 0.0 || (b - 1.0).abs() <= f64::EPSILON {
+                        Some(0)
+                    } else {
+                        (x <= 0.0).then_some(1)
+                    }
+                }
+                _ => None,
+            },
+            display: |name, args| format!("{}({})", name, args.join(",")),
+            ..exponential("log", |args| match *args {
+                [x] => x.log2(),
+                [b, x] => x.ln() / b.ln(),
+                _ => f64::NAN,
+            })
+        },
+        Builtin {
+            domain: not_positive,
+            rule: Rule::Logarithm,
+            ..exponential("log10", |args| args[0].log10())
+        },
+        Builtin {
+            domain: not_positive,
+            rule: Rule::Logarithm,
+            ..exponential("log2", |args| args[0].log2())
+        },
+        // exp(x) - 1 and ln(1 + x), precise for x near 0.
+        exponential("expm1", |args| args[0].exp_m1()),
+        Builtin {
+            domain: |args| (args[0] <= -1.0).then_some(0),
+            rule: Rule::Logarithm,
+            ..exponential("log1p", |args| args[0].ln_1p())
+        },
+        Builtin::unary("abs", |args| args[0].abs()),
+        Builtin {
+            domain: negative,
+            symbol: Some("√"),
+            ..Builtin::unary("sqrt", |args| args[0].sqrt())
+        },
+        Builtin {
+            domain: |args| (args[0] <= 0.0 && args[0].fract() == 0.0).then_some(0),
+            ..Builtin::unary("gamma", |args| gamma(args[0]))
+        },
+        Builtin {
+            arity: Arity::AtLeast(1),
+            usage: Some("min(a, b, ...)"),
+            describe: Some(|args| format!("take the smallest of {}", args.join(", "))),
+            ..Builtin::unary("min", |args| {
+                args.iter().copied().fold(f64::INFINITY, f64::min)
+            })
+        },
+        Builtin {
+            arity: Arity::AtLeast(1),
+            usage: Some("max(a, b, ...)"),
+            describe: Some(|args| format!("take the largest of {}", args.join(", "))),
+            ..Builtin::unary("max", |args| {
+                args.iter().copied().fold(f64::NEG_INFINITY, f64::max)
+            })
+        },
+        // root(n, x): the degree must be a non-zero whole number, and an
+        // even root needs a non-negative radicand.
+        Builtin {
+            arity: Arity::Exactly(2),
+            usage: Some("root(n, x)"),
+            domain: |args| {
+                let (n, x) = (args[0], args[1]);
+                if n == 0.0 || n.fract() != 0.0 {
+                    Some(0)
+                } else {
+                    (x < 0.0 && n % 2.0 == 0.0).then_some(1)
+                }
+            },
+            describe: Some(|args| format!("take the {} root of {}", ordinal(&args[0]), args[1])),
+            ..Builtin::unary("root", |args| root(args[0], args[1]))
+        },
+        Builtin {
+            arity: Arity::Exactly(2),
+            usage: Some("hypot(a, b)"),
+            describe: Some(|args| {
+                format!("find the hypotenuse of legs {} and {}", args[0], args[1])
+            }),
+            ..Builtin::unary("hypot", |args| args[0].hypot(args[1]))
+        },
+        // round(x) rounds to a whole number, round(x, digits) to decimals.
+        Builtin {
+            arity: Arity::Between(1, 2),
+            usage: Some("round(x, digits)"),
+            domain: |args| match *args {
+                [_, digits] => (digits.fract() != 0.0 || digits.abs() > 15.0).then_some(1),
+                _ => None,
+            },
+            describe: Some(|args| match args {
+                [x, digits] => format!("round {} to {} decimal places", x, digits),
+                _ => format!("round {} to a whole number", args[0]),
+            }),
+            ..Builtin::unary("round", |args| match *args {
+                [x, digits] => round_to(x, digits),
+                _ => args[0].round(),
+            })
+        },
+        // clamp(x, lo, hi): x limited to the interval [lo, hi].
+        Builtin {
+            arity: Arity::Exactly(3),
+            usage: Some("clamp(x, lo, hi)"),
+            domain: |args| (args[1] > args[2]).then_some(1),
+            describe: Some(|args| format!("clamp {} to [{}, {}]", args[0], args[1], args[2])),
+            ..Builtin::unary("clamp", |args| args[0].clamp(args[1], args[2]))
+        },
+    ]
+}
+
+/// `1st`, `2nd`, `3rd`, `4th`, … for a whole number, the number itself
+/// otherwise.
+fn ordinal(n: &str) -> String {
+    let Ok(value) = n.parse::<u64>() else {
+        return n.to_string();
+    };
+    let suffix = match (value % 10, value % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", n, suffix)
+}
+
+/// The `n`th root of `x`, negative for a negative `x` and an odd `n`.
+fn root(n: f64, x: f64) -> f64 {
+    if x < 0.0 {
+        -(-x).powf(1.0 / n)
+    } else {
+        x.powf(1.0 / n)
+    }
+}
+
+/// Rounds half away from zero to `digits` decimal places; a negative
+/// `digits` rounds to tens, hundreds, ….
+fn round_to(x: f64, digits: f64) -> f64 {
+    let scale = 10f64.powf(digits);
+    (x * scale).round() / scale
+}
+
+/// The gamma function, by the Lanczos approximation for `x >= 0.5` and the
+/// reflection formula below that. Infinite at its poles, the whole numbers
+/// `x <= 0`.
+pub fn gamma(x: f64) -> f64 {
+    if x < 0.5 {
+        return PI / ((PI * x).sin() * gamma(1.0 - x));
+    }
+    let x = x - 1.0;
+    let mut sum = LANCZOS_COEFFICIENTS[0];
+    for (i, coefficient) in LANCZOS_COEFFICIENTS.iter().enumerate().skip(1) {
+        sum += coefficient / (x + i as f64);
+    }
+    let t = x + LANCZOS_G + 0.5;
+    (2.0 * PI).sqrt() * t.powf(x + 0.5) * (-t).exp() * sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_lookup() {
+        let registry = FunctionRegistry::default();
+        let sqrt = registry.get("sqrt").unwrap();
+        assert_eq!((sqrt.implementation)(&[9.0]), 3.0);
+        assert_eq!((sqrt.domain)(&[-1.0]), Some(0));
+        assert!(registry.get("nope").is_none());
+        assert_eq!(registry.arities().get("log"), Some(&Arity::Between(1, 2)));
+    }
+
+    #[test]
+    fn test_register_replaces_by_name() {
+        let mut registry = FunctionRegistry::default();
+        let count = registry.iter().count();
+        registry.register(Builtin::unary("abs", |args| args[0]));
+        assert_eq!((registry.get("abs").unwrap().implementation)(&[-2.0]), -2.0);
+        assert_eq!(registry.iter().count(), count);
+    }
+
+    #[test]
+    fn test_aliases() {
+        let mut registry = FunctionRegistry::default();
+        for (alias, name) in [
+            ("tan", "tg"),
+            ("arctan", "atg"),
+            ("cot", "cotg"),
+            ("arcsin", "asin"),
+        ] {
+            assert_eq!(registry.get(alias).unwrap().name, name);
+        }
+        assert!(registry.add_alias("tangent", "tan"));
+        assert_eq!(registry.get("tangent").unwrap().name, "tg");
+        assert!(!registry.add_alias("x", "nope"));
+
+        let tg = registry.get("tg").unwrap();
+        assert_eq!(tg.display_name(FunctionNames::Anglophone), "tan");
+        assert_eq!(tg.display_name(FunctionNames::Continental), "tg");
+    }
+
+    #[test]
+    fn test_poles() {
+        let registry = FunctionRegistry::default();
+        let tg = registry.get("tg").unwrap();
+        assert_eq!((tg.domain)(&[PI / 2.0]), Some(0));
+        assert_eq!((tg.domain)(&[PI]), None);
+    }
+
+    #[test]
+    fn test_multi_argument_functions() {
+        let registry = FunctionRegistry::default();
+        let call = |name: &str, args: &[f64]| {
+            let builtin = registry.get(name).unwrap();
+            assert!(builtin.arity.accepts(args.len()));
+            match (builtin.domain)(args) {
+                Some(index) => Err(index),
+                None => Ok((builtin.implementation)(args)),
+            }
+        };
+        assert_eq!(call("min", &[3.0, 1.0, 2.0]), Ok(1.0));
+        assert_eq!(call("max", &[3.0]), Ok(3.0));
+        assert_eq!(call("root", &[3.0, -8.0]), Ok(-2.0));
+        assert_eq!(call("root", &[2.0, -8.0]), Err(1));
+        assert_eq!(call("root", &[0.5, 8.0]), Err(0));
+        assert_eq!(call("atan2", &[1.0, -1.0]), Ok(3.0 * PI / 4.0));
+        assert_eq!(call("atan2", &[0.0, 0.0]), Err(1));
+        assert_eq!(call("hypot", &[3.0, 4.0]), Ok(5.0));
+        assert_eq!(call("round", &[2.5]), Ok(3.0));
+        assert_eq!(call("round", &[1.23456, 2.0]), Ok(1.23));
+        assert_eq!(call("round", &[1234.0, -2.0]), Ok(1200.0));
+        assert_eq!(call("round", &[1.0, 0.5]), Err(1));
+        assert_eq!(call("clamp", &[5.0, 0.0, 3.0]), Ok(3.0));
+        assert_eq!(call("clamp", &[5.0, 3.0, 0.0]), Err(1));
+    }
+
+    #[test]
+    fn test_ordinals() {
+        assert_eq!(ordinal("1"), "1st");
+        assert_eq!(ordinal("3"), "3rd");
+        assert_eq!(ordinal("12"), "12th");
+        assert_eq!(ordinal("22"), "22nd");
+        assert_eq!(ordinal("2.5"), "2.5");
+    }
+
+    #[test]
+    fn test_hyperbolic_and_exponential_domains() {
+        let registry = FunctionRegistry::default();
+        let outside = |name: &str, x: f64| (registry.get(name).unwrap().domain)(&[x]).is_some();
+        assert!(outside("coth", 0.0));
+        assert!(outside("acosh", 0.5));
+        assert!(!outside("acosh", 1.0));
+        assert!(outside("atanh", 1.0));
+        assert!(outside("acoth", 0.5));
+        assert!(outside("log10", 0.0));
+        assert!(outside("log1p", -1.0));
+        assert!(!outside("expm1", -5.0));
+    }
+
+    #[test]
+    fn test_help_groups() {
+        let help = FunctionRegistry::default().help();
+        assert_eq!(
+            help[1],
+            "Hyperbolic functions: sinh, cosh, tanh, coth, asinh, acosh, atanh, acoth"
+        );
+        assert!(help.iter().any(|line| line.contains("log(base, number)")));
+    }
+
+    #[test]
+    fn test_arity_display() {
+        assert_eq!(Arity::Exactly(1).to_string(), "1 argument");
+        assert_eq!(Arity::Between(1, 2).to_string(), "1 to 2 arguments");
+        assert_eq!(Arity::AtLeast(2).to_string(), "at least 2 arguments");
+    }
+}
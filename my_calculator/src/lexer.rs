@@ -1,108 +1,434 @@
-#[derive(Debug, PartialEq, Clone, Copy)]
+use std::fmt;
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum Token {
     Number(f64),
+    /// A whole number written in hexadecimal, octal or binary, e.g. `0xff`.
+    Integer(u64),
     Pi,
     Euler,
     Plus,
     Minus,
     Multiply,
     Divide,
+    /// `//` or `div`, division rounded down to a whole number.
+    IntDivide,
+    /// `mod`, and `%` between two operands.
+    Modulo,
+    /// `%`. The parser reads it as a percent after an operand that ends the
+    /// term, e.g. `10%` in `200 + 10%`, and as `Modulo` otherwise.
+    Percent,
     Exponent,
-    Abs,
-    Sqrt,
-    Log,
-    Ln,
     Fact,
-    Sin,
-    Cos,
-    Tg,
-    Cotg,
-    Sec,
-    Csc,
-    Asin,
-    Acos,
-    Atg,
-    Actg,
+    /// `!!` after an operand.
+    DoubleFact,
+    /// `==`, `!=` and the orderings, which compare two values.
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    /// `and`, `or` and `not` of booleans.
+    And,
+    Or,
+    Not,
+    /// `true` or `false`.
+    Boolean(bool),
+    /// `&`, `|` and `xor` of the programmer mode.
+    BitAnd,
+    BitOr,
+    BitXor,
+    /// `~` before an operand.
+    BitNot,
+    ShiftLeft,
+    ShiftRight,
+    /// `!` before an operand. The lexer emits `Fact` for every single `!`;
+    /// the parser turns a prefix one into this.
+    Subfact,
+    /// `√` before an operand.
+    Sqrt,
     LParen,
     RParen,
     Comma,
+    /// `=` of an assignment such as `x = 5`.
+    Assign,
+    /// A name that is not a keyword: a variable such as `x` or `ans`, or a
+    /// function such as `sin`.
+    Identifier(String),
+    Deg,
+    Rad,
+    Grad,
     Eof,
 }
-#[derive(Clone, Debug)]
+
+/// Byte range `start..end` of a piece of the input expression.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+    /// Smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum LexErrorKind {
+    InputTooLarge,
+    UnrecognizedCharacter,
+    InvalidNumber,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct LexError {
+    pub kind: LexErrorKind,
+    /// Byte offset of the offending text in the input.
+    pub offset: usize,
+    /// 1-based column of the offending text, counted in characters.
+    pub column: usize,
+    pub text: String,
+}
+
+impl LexError {
+    fn new(kind: LexErrorKind, input: &str, offset: usize, text: &str) -> Self {
+        Self {
+            kind,
+            offset,
+            column: input[..offset].chars().count() + 1,
+            text: text.to_string(),
+        }
+    }
+    pub fn span(&self) -> Span {
+        Span::new(self.offset, self.offset + self.text.len())
+    }
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            LexErrorKind::InputTooLarge => write!(
+                f,
+                "The mathematical expression is too large! Please enter a reasonable expression!"
+            ),
+            LexErrorKind::UnrecognizedCharacter => write!(
+                f,
+                "Unrecognized character '{}' at column {}",
+                self.text, self.column
+            ),
+            LexErrorKind::InvalidNumber => {
+                write!(
+                    f,
+                    "Invalid number '{}' at column {}",
+                    self.text, self.column
+                )
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
 pub struct Lexer {
-    pub tokens: Vec<Token>,
+    pub tokens: Vec<SpannedToken>,
 }
 impl Lexer {
     pub fn new() -> Self {
         Self { tokens: Vec::new() }
     }
-    pub fn tokenize(&mut self, input: &str) {
+    fn push(&mut self, token: Token, start: usize, end: usize) {
+        self.tokens.push(SpannedToken {
+            token,
+            span: Span::new(start, end),
+        });
+    }
+    /// The token of a constant, angle unit or operator name. Function names are
+    /// identifiers, looked up by the parser.
+    fn keyword(word: &str) -> Option<Token> {
+        let token = match word {
+            "pi" => Token::Pi,
+            "e" => Token::Euler,
+            "deg" => Token::Deg,
+            "rad" => Token::Rad,
+            "grad" => Token::Grad,
+            "xor" => Token::BitXor,
+            "mod" => Token::Modulo,
+            "div" => Token::IntDivide,
+            "and" => Token::And,
+            "or" => Token::Or,
+            "not" => Token::Not,
+            "true" => Token::Boolean(true),
+            "false" => Token::Boolean(false),
+            _ => return None,
+        };
+        Some(token)
+    }
+    /// The digit or minus sign a superscript character stands for.
+    fn superscript(ch: char) -> Option<char> {
+        let plain = match ch {
+            '⁰' => '0',
+            '¹' => '1',
+            '²' => '2',
+            '³' => '3',
+            '⁴' => '4',
+            '⁵' => '5',
+            '⁶' => '6',
+            '⁷' => '7',
+            '⁸' => '8',
+            '⁹' => '9',
+            '⁻' => '-',
+            _ => return None,
+        };
+        Some(plain)
+    }
+    /// The radix and length of a `0x`, `0o` or `0b` literal at the start of
+    /// `rest`, or `None` if there is none. The prefix must be followed by a
+    /// digit of its base, so `0b2` is still `0 * b2`.
+    fn radix_literal(rest: &str) -> Option<(u32, usize)> {
+        let radix = match rest.get(..2)? {
+            "0x" => 16,
+            "0o" => 8,
+            "0b" => 2,
+            _ => return None,
+        };
+        let digits = rest[2..]
+            .chars()
+            .take_while(|ch| ch.is_digit(radix))
+            .count();
+        (digits > 0).then_some((radix, 2 + digits))
+    }
+    /// Length of the exponent part of a scientific-notation literal at the
+    /// start of `rest`, or 0 if there is none.
+    ///
+    /// An `e` only starts an exponent when it is immediately followed by
+    /// digits, optionally after a sign: `2e3` and `2e-3` are literals, while
+    /// `2e`, `2 e` and `2e + 3` use the constant e.
+    fn exponent_length(rest: &str) -> usize {
+        let bytes = rest.as_bytes();
+        if bytes.first() != Some(&b'e') {
+            return 0;
+        }
+        let sign = matches!(bytes.get(1), Some(b'+') | Some(b'-')) as usize;
+        let digits = bytes[1 + sign..]
+            .iter()
+            .take_while(|b| b.is_ascii_digit())
+            .count();
+        if digits == 0 {
+            0
+        } else {
+            1 + sign + digits
+        }
+    }
+    pub fn tokenize(&mut self, input: &str) -> Result<Vec<SpannedToken>, LexError> {
+        self.tokens.clear();
         if input.len() > 10_000 {
-            eprintln!(
-                "The mathematical expression is too large! Please enter a reasonable expression!"
-            );
-            return;
+            return Err(LexError::new(LexErrorKind::InputTooLarge, input, 0, ""));
         }
         let input = input.to_ascii_lowercase();
-        let mut chars = input.chars().peekable();
+        let mut chars = input.char_indices().peekable();
         let mut buffer = String::new();
 
-        self.tokens.clear();
-
-        while let Some(&ch) = chars.peek() {
+        while let Some(&(start, ch)) = chars.peek() {
             match ch {
                 '+' => {
-                    self.tokens.push(Token::Plus);
+                    self.push(Token::Plus, start, start + 1);
+                    chars.next();
+                }
+                '-' | '−' => {
+                    self.push(Token::Minus, start, start + ch.len_utf8());
                     chars.next();
                 }
-                '-' => {
-                    self.tokens.push(Token::Minus);
+                '*' | '×' | '·' => {
+                    self.push(Token::Multiply, start, start + ch.len_utf8());
                     chars.next();
                 }
-                '*' => {
-                    self.tokens.push(Token::Multiply);
+                '/' if input[start + 1..].starts_with('/') => {
+                    self.push(Token::IntDivide, start, start + 2);
+                    chars.next();
                     chars.next();
                 }
-                '/' => {
-                    self.tokens.push(Token::Divide);
+                '/' | '÷' => {
+                    self.push(Token::Divide, start, start + ch.len_utf8());
+                    chars.next();
+                }
+                '%' => {
+                    self.push(Token::Percent, start, start + 1);
+                    chars.next();
+                }
+                'π' => {
+                    self.push(Token::Pi, start, start + ch.len_utf8());
+                    chars.next();
+                }
+                '√' => {
+                    self.push(Token::Sqrt, start, start + ch.len_utf8());
                     chars.next();
                 }
                 '(' => {
-                    self.tokens.push(Token::LParen);
+                    self.push(Token::LParen, start, start + 1);
                     chars.next();
                 }
                 ')' => {
-                    self.tokens.push(Token::RParen);
+                    self.push(Token::RParen, start, start + 1);
                     chars.next();
                 }
                 '^' => {
-                    self.tokens.push(Token::Exponent);
+                    self.push(Token::Exponent, start, start + 1);
+                    chars.next();
+                }
+                '=' | '!' | '<' | '>' if input[start + 1..].starts_with('=') => {
+                    let token = match ch {
+                        '=' => Token::Equal,
+                        '!' => Token::NotEqual,
+                        '<' => Token::LessEqual,
+                        _ => Token::GreaterEqual,
+                    };
+                    self.push(token, start, start + 2);
+                    chars.next();
+                    chars.next();
+                }
+                '=' => {
+                    self.push(Token::Assign, start, start + 1);
+                    chars.next();
+                }
+                '≠' | '≤' | '≥' => {
+                    let token = match ch {
+                        '≠' => Token::NotEqual,
+                        '≤' => Token::LessEqual,
+                        _ => Token::GreaterEqual,
+                    };
+                    self.push(token, start, start + ch.len_utf8());
+                    chars.next();
+                }
+                '&' => {
+                    self.push(Token::BitAnd, start, start + 1);
+                    chars.next();
+                }
+                '|' => {
+                    self.push(Token::BitOr, start, start + 1);
+                    chars.next();
+                }
+                '~' => {
+                    self.push(Token::BitNot, start, start + 1);
+                    chars.next();
+                }
+                '<' | '>' if input[start + 1..].starts_with(ch) => {
+                    let token = if ch == '<' {
+                        Token::ShiftLeft
+                    } else {
+                        Token::ShiftRight
+                    };
+                    self.push(token, start, start + 2);
+                    chars.next();
+                    chars.next();
+                }
+                '<' => {
+                    self.push(Token::Less, start, start + 1);
+                    chars.next();
+                }
+                '>' => {
+                    self.push(Token::Greater, start, start + 1);
                     chars.next();
                 }
                 '!' => {
-                    self.tokens.push(Token::Fact);
                     chars.next();
+                    if let Some(&(_, '!')) = chars.peek() {
+                        chars.next();
+                        self.push(Token::DoubleFact, start, start + 2);
+                    } else {
+                        self.push(Token::Fact, start, start + 1);
+                    }
+                }
+                // A run of superscripts is a power: `x²` is `x ^ 2`, `10⁻³`
+                // is `10 ^ -3`.
+                _ if Self::superscript(ch).is_some() => {
+                    buffer.clear();
+                    let mut end = start;
+                    while let Some(plain) = chars.peek().and_then(|&(_, ch)| Self::superscript(ch))
+                    {
+                        if let Some((index, ch)) = chars.next() {
+                            end = index + ch.len_utf8();
+                        }
+                        buffer.push(plain);
+                    }
+                    // Only a single leading minus: `2⁻⁻²` and `2²⁻³` are errors.
+                    let digits = buffer.strip_prefix('-').unwrap_or(&buffer);
+                    let Some(power) = digits
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|_| digits.bytes().all(|byte| byte.is_ascii_digit()))
+                    else {
+                        return Err(LexError::new(
+                            LexErrorKind::InvalidNumber,
+                            &input,
+                            start,
+                            &input[start..end],
+                        ));
+                    };
+                    self.push(Token::Exponent, start, end);
+                    if digits.len() < buffer.len() {
+                        self.push(Token::Minus, start, end);
+                    }
+                    self.push(Token::Number(power), start, end);
                 }
                 '0'..='9' | '.' => {
+                    if let Some((radix, length)) = Self::radix_literal(&input[start..]) {
+                        let text = &input[start..start + length];
+                        let Ok(value) = u64::from_str_radix(&text[2..], radix) else {
+                            return Err(LexError::new(
+                                LexErrorKind::InvalidNumber,
+                                &input,
+                                start,
+                                text,
+                            ));
+                        };
+                        self.push(Token::Integer(value), start, start + length);
+                        for _ in 0..length {
+                            chars.next();
+                        }
+                        continue;
+                    }
                     buffer.clear();
-                    while let Some(&ch) = chars.peek() {
-                        if ch.is_numeric() || ch == '.' {
+                    while let Some(&(_, ch)) = chars.peek() {
+                        if ch.is_ascii_digit() || ch == '.' {
                             buffer.push(ch);
                             chars.next();
                         } else {
                             break;
                         }
                     }
-                    if let Ok(number) = buffer.parse::<f64>() {
-                        self.tokens.push(Token::Number(number));
-                    } else {
-                        eprintln!("Invalid number: {}", buffer);
+                    let exponent = Self::exponent_length(&input[start + buffer.len()..]);
+                    for _ in 0..exponent {
+                        if let Some((_, ch)) = chars.next() {
+                            buffer.push(ch);
+                        }
+                    }
+                    match buffer.parse::<f64>() {
+                        Ok(number) => self.push(Token::Number(number), start, start + buffer.len()),
+                        Err(_) => {
+                            return Err(LexError::new(
+                                LexErrorKind::InvalidNumber,
+                                &input,
+                                start,
+                                &buffer,
+                            ))
+                        }
                     }
                 }
                 'a'..='z' => {
                     buffer.clear();
-                    while let Some(&ch) = chars.peek() {
-                        if ch.is_alphabetic() {
+                    while let Some(&(_, ch)) = chars.peek() {
+                        if ch.is_ascii_alphabetic() {
                             buffer.push(ch);
                             chars.next();
                         } else {
@@ -110,57 +436,69 @@ impl Lexer {
                         }
                     }
 
-                    match buffer.as_str() {
-                        "abs" => self.tokens.push(Token::Abs),
-                        "sqrt" => self.tokens.push(Token::Sqrt),
-                        "log" => self.tokens.push(Token::Log),
-                        "ln" => self.tokens.push(Token::Ln),
-                        "sin" => self.tokens.push(Token::Sin),
-                        "cos" => self.tokens.push(Token::Cos),
-                        "tg" => self.tokens.push(Token::Tg),
-                        "cotg" => self.tokens.push(Token::Cotg),
-                        "sec" => self.tokens.push(Token::Sec),
-                        "csc" => self.tokens.push(Token::Csc),
-                        "asin" => self.tokens.push(Token::Asin),
-                        "acos" => self.tokens.push(Token::Acos),
-                        "atg" => self.tokens.push(Token::Atg),
-                        "actg" => self.tokens.push(Token::Actg),
-                        "pi" => self.tokens.push(Token::Pi),
-                        "e" => self.tokens.push(Token::Euler),
-                        _ => eprintln!("Invalid keyword: {}", buffer),
-                    }
+                    // A keyword ends at the first digit, so a detached `e3`
+                    // is the constant e followed by the number 3 (`2e3` never
+                    // gets here, the number takes its exponent); any other
+                    // word is an identifier, which may go on with digits and
+                    // underscores, as in `ans2`.
+                    let token = match Self::keyword(&buffer) {
+                        Some(token) => token,
+                        None => {
+                            while let Some(&(_, ch)) = chars.peek() {
+                                if ch.is_ascii_alphanumeric() || ch == '_' {
+                                    buffer.push(ch);
+                                    chars.next();
+                                } else {
+                                    break;
+                                }
+                            }
+                            Token::Identifier(buffer.clone())
+                        }
+                    };
+                    self.push(token, start, start + buffer.len());
                 }
                 ',' => {
-                    self.tokens.push(Token::Comma);
+                    self.push(Token::Comma, start, start + 1);
+                    chars.next();
+                }
+                '°' => {
+                    self.push(Token::Deg, start, start + ch.len_utf8());
                     chars.next();
                 }
                 ' ' => {
                     chars.next();
                 }
                 _ => {
-                    eprintln!("Unrecongnized character: {}", ch);
-                    chars.next();
+                    return Err(LexError::new(
+                        LexErrorKind::UnrecognizedCharacter,
+                        &input,
+                        start,
+                        &ch.to_string(),
+                    ));
                 }
             }
         }
-        self.tokens.push(Token::Eof);
+        self.push(Token::Eof, input.len(), input.len());
+        Ok(self.tokens.clone())
     }
-    //pub fn get_tokens(self) -> Vec<Token> {
-    //   self.tokens.clone()
-    //}
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn kinds(tokens: Vec<SpannedToken>) -> Vec<Token> {
+        tokens.into_iter().map(|t| t.token).collect()
+    }
+
     #[test]
     fn check_basic_tokens() {
         let input = String::from("2 + 3 * 4 ^ 2");
         let mut lexer = Lexer::new();
-        lexer.tokenize(&input);
+        let tokens = kinds(lexer.tokenize(&input).unwrap());
+
         assert_eq!(
-            lexer.tokens,
+            tokens,
             vec![
                 Token::Number(2.0),
                 Token::Plus,
@@ -177,22 +515,22 @@ mod tests {
     fn check_trigonometric_tokens() {
         let input = "sin(90) + cos(0) - sec(45)";
         let mut lexer = Lexer::new();
-        lexer.tokenize(&input);
+        let tokens = kinds(lexer.tokenize(input).unwrap());
 
         assert_eq!(
-            lexer.tokens,
+            tokens,
             vec![
-                Token::Sin,
+                Token::Identifier("sin".to_string()),
                 Token::LParen,
                 Token::Number(90.0),
                 Token::RParen,
                 Token::Plus,
-                Token::Cos,
+                Token::Identifier("cos".to_string()),
                 Token::LParen,
                 Token::Number(0.0),
                 Token::RParen,
                 Token::Minus,
-                Token::Sec,
+                Token::Identifier("sec".to_string()),
                 Token::LParen,
                 Token::Number(45.0),
                 Token::RParen,
@@ -204,35 +542,65 @@ mod tests {
     fn check_invalid_characters() {
         let input = "2 + 3 # 4";
         let mut lexer = Lexer::new();
-        lexer.tokenize(&input);
+        let error = lexer.tokenize(input).unwrap_err();
 
         assert_eq!(
-            lexer.tokens,
+            error,
+            LexError {
+                kind: LexErrorKind::UnrecognizedCharacter,
+                offset: 6,
+                column: 7,
+                text: "#".to_string(),
+            }
+        )
+    }
+
+    #[test]
+    fn check_identifiers() {
+        let mut lexer = Lexer::new();
+
+        let tokens = kinds(lexer.tokenize("rate_2 = 3 * ans1 + Ex").unwrap());
+        assert_eq!(
+            tokens,
             vec![
-                Token::Number(2.0),
-                Token::Plus,
+                Token::Identifier("rate_2".to_string()),
+                Token::Assign,
                 Token::Number(3.0),
-                Token::Number(4.0),
-                Token::Eof
+                Token::Multiply,
+                Token::Identifier("ans1".to_string()),
+                Token::Plus,
+                Token::Identifier("ex".to_string()),
+                Token::Eof,
             ]
-        )
+        );
+    }
+
+    #[test]
+    fn check_invalid_number() {
+        let input = "1 + 1.2.3";
+        let mut lexer = Lexer::new();
+        let error = lexer.tokenize(input).unwrap_err();
+
+        assert_eq!(error.kind, LexErrorKind::InvalidNumber);
+        assert_eq!(error.column, 5);
+        assert_eq!(error.text, "1.2.3");
     }
 
     #[test]
     fn check_function_tokens() {
         let input = "log(10) + sqrt(16)";
         let mut lexer = Lexer::new();
-        lexer.tokenize(input);
+        let tokens = kinds(lexer.tokenize(input).unwrap());
 
         assert_eq!(
-            lexer.tokens,
+            tokens,
             vec![
-                Token::Log,
+                Token::Identifier("log".to_string()),
                 Token::LParen,
                 Token::Number(10.0),
                 Token::RParen,
                 Token::Plus,
-                Token::Sqrt,
+                Token::Identifier("sqrt".to_string()),
                 Token::LParen,
                 Token::Number(16.0),
                 Token::RParen,
@@ -245,11 +613,300 @@ mod tests {
     fn check_constants() {
         let input = "pi + e";
         let mut lexer = Lexer::new();
-        lexer.tokenize(input);
+        let tokens = kinds(lexer.tokenize(input).unwrap());
 
         assert_eq!(
-            lexer.tokens,
+            tokens,
             vec![Token::Pi, Token::Plus, Token::Euler, Token::Eof]
         );
     }
+
+    #[test]
+    fn check_scientific_notation() {
+        let mut lexer = Lexer::new();
+
+        let tokens = kinds(lexer.tokenize("6.022e23 + 1.5E-3").unwrap());
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Number(6.022e23),
+                Token::Plus,
+                Token::Number(1.5e-3),
+                Token::Eof
+            ]
+        );
+
+        let tokens = kinds(lexer.tokenize("2e3 - 2e+3").unwrap());
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Number(2000.0),
+                Token::Minus,
+                Token::Number(2000.0),
+                Token::Eof
+            ]
+        );
+    }
+
+    #[test]
+    fn check_euler_next_to_numbers() {
+        let mut lexer = Lexer::new();
+
+        let tokens = kinds(lexer.tokenize("2e").unwrap());
+        assert_eq!(tokens, vec![Token::Number(2.0), Token::Euler, Token::Eof]);
+
+        // The parser then rejects the 3, as a number never multiplies
+        // implicitly.
+        let tokens = kinds(lexer.tokenize("2 e3").unwrap());
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Number(2.0),
+                Token::Euler,
+                Token::Number(3.0),
+                Token::Eof
+            ]
+        );
+
+        let tokens = kinds(lexer.tokenize("2e + 3").unwrap());
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Number(2.0),
+                Token::Euler,
+                Token::Plus,
+                Token::Number(3.0),
+                Token::Eof
+            ]
+        );
+    }
+
+    #[test]
+    fn check_angle_units() {
+        let mut lexer = Lexer::new();
+
+        let tokens = kinds(
+            lexer
+                .tokenize("sin(30deg) + cos(1.2rad) - tg(50 grad) * 45°")
+                .unwrap(),
+        );
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Identifier("sin".to_string()),
+                Token::LParen,
+                Token::Number(30.0),
+                Token::Deg,
+                Token::RParen,
+                Token::Plus,
+                Token::Identifier("cos".to_string()),
+                Token::LParen,
+                Token::Number(1.2),
+                Token::Rad,
+                Token::RParen,
+                Token::Minus,
+                Token::Identifier("tg".to_string()),
+                Token::LParen,
+                Token::Number(50.0),
+                Token::Grad,
+                Token::RParen,
+                Token::Multiply,
+                Token::Number(45.0),
+                Token::Deg,
+                Token::Eof
+            ]
+        );
+    }
+
+    #[test]
+    fn check_factorial_tokens() {
+        let mut lexer = Lexer::new();
+
+        let tokens = kinds(lexer.tokenize("5!! + !3 + gamma(2)").unwrap());
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Number(5.0),
+                Token::DoubleFact,
+                Token::Plus,
+                Token::Fact,
+                Token::Number(3.0),
+                Token::Plus,
+                Token::Identifier("gamma".to_string()),
+                Token::LParen,
+                Token::Number(2.0),
+                Token::RParen,
+                Token::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn check_token_spans() {
+        let input = "sqrt(16) + 2.5";
+        let mut lexer = Lexer::new();
+        let spans: Vec<Span> = lexer
+            .tokenize(input)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.span)
+            .collect();
+
+        assert_eq!(
+            spans,
+            vec![
+                Span::new(0, 4),
+                Span::new(4, 5),
+                Span::new(5, 7),
+                Span::new(7, 8),
+                Span::new(9, 10),
+                Span::new(11, 14),
+                Span::new(14, 14)
+            ]
+        );
+    }
+
+    #[test]
+    fn check_unicode_operators() {
+        let mut lexer = Lexer::new();
+        assert_eq!(
+            kinds(lexer.tokenize("2×3·4÷π−√9").unwrap()),
+            vec![
+                Token::Number(2.0),
+                Token::Multiply,
+                Token::Number(3.0),
+                Token::Multiply,
+                Token::Number(4.0),
+                Token::Divide,
+                Token::Pi,
+                Token::Minus,
+                Token::Sqrt,
+                Token::Number(9.0),
+                Token::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn check_superscripts() {
+        let mut lexer = Lexer::new();
+        assert_eq!(
+            kinds(lexer.tokenize("x² + 10⁻¹²").unwrap()),
+            vec![
+                Token::Identifier("x".to_string()),
+                Token::Exponent,
+                Token::Number(2.0),
+                Token::Plus,
+                Token::Number(10.0),
+                Token::Exponent,
+                Token::Minus,
+                Token::Number(12.0),
+                Token::Eof,
+            ]
+        );
+        let error = lexer.tokenize("2⁻").unwrap_err();
+        assert_eq!(error.kind, LexErrorKind::InvalidNumber);
+        assert_eq!(error.column, 2);
+        for input in ["2⁻⁻²", "2²⁻³"] {
+            let error = lexer.tokenize(input).unwrap_err();
+            assert_eq!(error.kind, LexErrorKind::InvalidNumber);
+        }
+    }
+
+    #[test]
+    fn check_programmer_tokens() {
+        let mut lexer = Lexer::new();
+        assert_eq!(
+            kinds(
+                lexer
+                    .tokenize("0xFF & 0b1010 | ~0o17 xor 1 << 2 >> 3")
+                    .unwrap()
+            ),
+            vec![
+                Token::Integer(255),
+                Token::BitAnd,
+                Token::Integer(10),
+                Token::BitOr,
+                Token::BitNot,
+                Token::Integer(15),
+                Token::BitXor,
+                Token::Number(1.0),
+                Token::ShiftLeft,
+                Token::Number(2.0),
+                Token::ShiftRight,
+                Token::Number(3.0),
+                Token::Eof,
+            ]
+        );
+        // Without a digit of its base after it, the prefix is not a literal.
+        assert_eq!(
+            kinds(lexer.tokenize("0b2").unwrap()),
+            vec![
+                Token::Number(0.0),
+                Token::Identifier("b2".to_string()),
+                Token::Eof,
+            ]
+        );
+        let error = lexer.tokenize("0x1ffffffffffffffff").unwrap_err();
+        assert_eq!(error.kind, LexErrorKind::InvalidNumber);
+    }
+
+    #[test]
+    fn check_division_and_percent_tokens() {
+        let mut lexer = Lexer::new();
+        assert_eq!(
+            kinds(lexer.tokenize("7 // 2 div 3 mod 4 % 5%").unwrap()),
+            vec![
+                Token::Number(7.0),
+                Token::IntDivide,
+                Token::Number(2.0),
+                Token::IntDivide,
+                Token::Number(3.0),
+                Token::Modulo,
+                Token::Number(4.0),
+                Token::Percent,
+                Token::Number(5.0),
+                Token::Percent,
+                Token::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn check_comparison_tokens() {
+        let mut lexer = Lexer::new();
+        assert_eq!(
+            kinds(lexer.tokenize("1 == 2 != 3 < 4 <= 5 > 6 >= 7 ≠ 8").unwrap()),
+            vec![
+                Token::Number(1.0),
+                Token::Equal,
+                Token::Number(2.0),
+                Token::NotEqual,
+                Token::Number(3.0),
+                Token::Less,
+                Token::Number(4.0),
+                Token::LessEqual,
+                Token::Number(5.0),
+                Token::Greater,
+                Token::Number(6.0),
+                Token::GreaterEqual,
+                Token::Number(7.0),
+                Token::NotEqual,
+                Token::Number(8.0),
+                Token::Eof,
+            ]
+        );
+        assert_eq!(
+            kinds(lexer.tokenize("not true and x or false").unwrap()),
+            vec![
+                Token::Not,
+                Token::Boolean(true),
+                Token::And,
+                Token::Identifier("x".to_string()),
+                Token::Or,
+                Token::Boolean(false),
+                Token::Eof,
+            ]
+        );
+    }
 }
@@ -0,0 +1,12 @@
+pub mod bigint;
+pub mod diagnostics;
+pub mod environment;
+pub mod evaluator;
+pub mod functions;
+pub mod lexer;
+pub mod parser;
+pub mod rational;
+pub mod settings;
+pub mod sink;
+pub mod step;
+pub mod utils;
@@ -1,18 +1,20 @@
-mod evaluator;
-mod lexer;
-mod parser;
-mod utils;
-
-use evaluator::Evaluator;
-use lexer::Lexer;
-use parser::Parser;
+use my_calculator::diagnostics;
+use my_calculator::environment::{Environment, UserFunction};
+use my_calculator::evaluator::Evaluator;
+use my_calculator::functions::FunctionRegistry;
+use my_calculator::lexer::Lexer;
+use my_calculator::parser::{Parser, Statement};
+use my_calculator::settings::Settings;
+use my_calculator::sink::{StdoutSink, TraceSink};
+use my_calculator::utils::{delete_saved_evaluations, save_to_file};
 use std::io::{self, Write};
 use std::{thread, time};
-use utils::{delete_saved_evaluations, save_to_file};
 
 fn main() {
     println!("Welcome to the Step-by-Step Calculator!");
     println!("This calculator evaluates mathematical expressions step by step!");
+    let mut settings = Settings::default();
+    let mut environment = Environment::new();
     loop {
         println!("\nMain Menu:");
         println!("1. Start a new calculation");
@@ -30,7 +32,7 @@ fn main() {
         let choice = choice.trim();
 
         match choice {
-            "1" => start_calculator(),
+            "1" => start_calculator(&mut settings, &mut environment),
             "2" => show_available_commands(),
             "3" => match delete_saved_evaluations() {
                 Ok(_) => println!("All saved evaluations have been deleted successfully"),
@@ -45,10 +47,12 @@ fn main() {
     }
 }
 
-fn start_calculator() {
+fn start_calculator(settings: &mut Settings, environment: &mut Environment) {
     loop {
         let mut input = String::new();
-        print!("Enter a mathematical expression (or type \"quit\" to return or \"help\" for help): ");
+        print!(
+            "Enter a mathematical expression (or type \"quit\" to return or \"help\" for help): "
+        );
 
         io::stdout().flush().unwrap();
         io::stdin()
@@ -61,27 +65,67 @@ fn start_calculator() {
         } else if input.eq_ignore_ascii_case("help") {
             show_available_commands();
             continue;
+        } else if input.eq_ignore_ascii_case("set") {
+            println!("Settings: {}", settings);
+            continue;
+        } else if let Some(command) = input.strip_prefix("set ") {
+            match settings.apply(command) {
+                Ok(message) => println!("{}", message),
+                Err(err) => eprintln!("Error: {}", err),
+            }
+            continue;
         } else if input.is_empty() {
             eprintln!("Please enter a non-empty expression!");
             continue;
         }
-        
+
         let mut lexer = Lexer::new();
-        lexer.tokenize(input);
-        //println!("Tokens: {:?}", lexer.tokens);
+        let tokens = match lexer.tokenize(input) {
+            Ok(tokens) => tokens,
+            Err(err) => {
+                eprintln!(
+                    "{}",
+                    diagnostics::render(input, err.span(), &err.to_string())
+                );
+                continue;
+            }
+        };
 
-        let mut parser = Parser::new(lexer.tokens.clone());
-        let mut evaluator = Evaluator::new();
-        match parser.parse_expression() {
-            Ok(ast) => {
+        let mut parser = Parser::with_environment(tokens, environment);
+        let evaluator = Evaluator::with_environment(settings.clone(), environment.clone());
+        match parser.parse_statement() {
+            Ok(statement) => {
+                let (name, ast) = match statement {
+                    Statement::Expression(ast) => (None, ast),
+                    Statement::Assignment { name, value } => (Some(name), value),
+                    Statement::Definition {
+                        name,
+                        parameters,
+                        body,
+                    } => {
+                        println!("Defined {}({})", name, parameters.join(", "));
+                        environment.define(&name, UserFunction { parameters, body });
+                        continue;
+                    }
+                };
                 println!("Evaluating...");
-                match evaluator.evaluate_and_print(ast) {
-                    Ok(_) => println!("Evaluation Complete!"),
+                let trace = match evaluator.evaluate(ast) {
+                    Ok(trace) => trace,
                     Err(err) => {
-                        println!("Error: {}", err);
+                        eprintln!("{}", diagnostics::render(input, err.span, &err.to_string()));
                         continue;
                     }
+                };
+                if let Err(e) = StdoutSink.write_trace(&trace) {
+                    eprintln!("Failed to print evaluation: {}", e);
                 }
+                environment.push_result(trace.value.clone());
+                if let Some(name) = name {
+                    let shown = trace.lines().pop().unwrap_or_default();
+                    println!("{} {}", name, shown);
+                    environment.assign(&name, trace.value.clone());
+                }
+                println!("Evaluation Complete!");
                 println!("Would you like to save this evaluation process?(y/n)");
                 let mut answer = String::new();
                 io::stdin()
@@ -95,13 +139,13 @@ fn start_calculator() {
                         .read_line(&mut file_name)
                         .expect("Failed to read input");
                     let file_name = file_name.trim();
-                    match save_to_file(file_name, &evaluator.get_evaluation_steps()) {
+                    match save_to_file(file_name, &trace) {
                         Ok(_) => println!("Evaluation saved succesfully."),
                         Err(e) => eprintln!("Failed to save file: {}", e),
                     }
                 }
             }
-            Err(err) => eprintln!("Error: {}", err),
+            Err(err) => eprintln!("{}", diagnostics::render(input, err.span, &err.message)),
         }
     }
 }
@@ -109,14 +153,61 @@ fn start_calculator() {
 fn show_available_commands() {
     println!("\nAvailable Calculator operators:");
     println!("- Basic arithmetic operators: +, -, *, /");
-    println!("- Exponentiation: ^ (e.g., 2 ^ 3)");
-    println!("- Trigonometric functions: sin, cos, tg, cotg, sec, csc, asin, acos, atg, actg (in degrees)");
-    println!("- Logarithmic functions: log(base, number), ln");
-    println!("- Square root: sqrt");
-    println!("- Absolute value: abs");
-    println!("- Factorial: !");
+    println!("- Remainder: 7 % 3 or 7 mod 3 = 1; integer division: 7 // 2 or 7 div 2 = 3");
+    println!("  Both round the quotient down, so the remainder has the sign of the divisor:");
+    println!("  -7 mod 3 = 2 and -7 // 2 = -4");
+    println!("- Percent: 20% = 0.2, but 200 + 10% = 220 and 200 - 10% = 180 (10% of 200)");
+    println!("  A % with an operand after it is a remainder: 7 % 3 = 1 and 7 % -3 = -2,");
+    println!("  but 10% - 5 and 10%-5 subtract 5 from a percent");
+    println!("- Exponentiation: ^ (e.g., 2 ^ 3), right-associative: 2 ^ 3 ^ 2 = 2 ^ 9");
+    println!("- Unary minus binds looser than ^: -2 ^ 2 = -(2 ^ 2) = -4");
+    println!("- Real roots of negative bases for odd denominators: (-8)^(1/3) = -2");
+    println!("- Built-in functions:");
+    for line in FunctionRegistry::default().help() {
+        println!("  {}", line);
+    }
+    println!("  Angles use the angle mode (degrees by default); a suffix overrides it:");
+    println!("  30deg or 30°, 1.2rad, 50grad. log(x) is the base 2 logarithm.");
+    println!("  English spellings work too: tan, cot, atan, acot, arcsin, arctan, ...");
+    println!("- Factorial: 5!, double factorial: 7!! = 7·5·3·1, subfactorial: !4 = 9");
     println!("- Constants: pi (3.14159), e (2.71828)");
+    println!("- Variables: x = 5 assigns, later lines can use x (e.g. 2x + 1)");
+    println!("- Functions: f(x, r) = x * (1 + r)^2 defines f, call it as f(1000, 0.05)");
+    println!("- Earlier results: ans or ans1 is the last result, ans2 the one before, ...");
+    println!("- Scientific notation: 6.022e23, 1.5e-3 (write 2 * e or 2 e + 3 for the constant)");
+    println!("  No space before the exponent: 2 e3 is an error, not 2000");
     println!("- Parentheses for grouping: ( and )");
+    println!("- Programmer mode (set programmer i32): 0xff, 0o17 and 0b1011 literals,");
+    println!("  bitwise & | xor ~ and shifts << >>, on wrapping fixed-width integers");
+    println!("- Unicode input: × and · multiply, ÷ divides, − subtracts, π, √9 or √(x + 1),");
+    println!("  and superscript powers such as x² or 10⁻³");
+    println!("- Comparisons: == != < <= > >= (or ≠ ≤ ≥) give true or false, which combine");
+    println!("  with and, or and not: sin(30)^2 + cos(30)^2 == 1 and not 2 > 3");
+    println!("- Implicit multiplication: 2pi, 3(4 + 5), (1 + 2)(3 + 4), 2sin(30)");
+    println!("  It binds tighter than * and / but looser than ^:");
+    println!("  1/2pi = 1 / (2 * pi) and 2^3pi = (2 ^ 3) * pi");
+    println!("\nSettings (type \"set\" to see the current values):");
+    println!("- set precision <n>: show results with n decimal places (default 2)");
+    println!("- set digits <n>: show results with n significant digits");
+    println!("- set school on|off: round every intermediate result to the shown precision");
+    println!("- set angle deg|rad|grad: unit of trigonometric arguments and inverse results");
+    println!("- set exact on|off: keep + - * / and integer powers as exact fractions");
+    println!(
+        "- set maxdigits <n>: largest exact integer result (factorials, powers), default 1000"
+    );
+    println!("- set gamma on|off: evaluate x! for real x as gamma(x + 1)");
+    println!(
+        "- set names continental|anglophone: show tg, cotg, atg, actg or tan, cot, atan, acot"
+    );
+    println!("- set output unicode|math|ascii: write the steps as 2 * π, sqrt(x), x ^ 2");
+    println!("  (default), as 2 × π, √(x), x² or in plain ASCII as 2 * pi, sqrt(x), x ^ 2");
+    println!("- set programmer off|i8|i16|i32|i64|u8|u16|u32|u64: integer-only programmer");
+    println!("  mode on two's-complement integers of that type (default off)");
+    println!("- set base dec|hex|oct|bin: base of the numbers in programmer mode steps");
+    println!("- set tolerance <x>: floats are equal when they differ by at most x relative");
+    println!("  to the larger of them and 1 (default 1e-9)");
+    println!("- set steps strict|level: one operation per step (the leftmost innermost one),");
+    println!("  or every operation whose operands are already numbers at once");
     println!("\nFunctions of the application and Commands:");
     println!("- Step-by-step evaluation of expressions.");
     println!("- Save evaluations to files.");
@@ -1,247 +1,559 @@
-use crate::lexer::Token;
+use crate::bigint::BigInt;
+use crate::environment::Environment;
+use crate::functions::{Arity, FunctionRegistry};
+use crate::lexer::{Span, SpannedToken, Token};
+use crate::rational::Rational;
+use crate::settings::AngleMode;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum ASTNode {
     Number(f64),
+    /// An exact fraction, used by the exact arithmetic mode.
+    Fraction(Rational),
+    /// A whole number kept exact: one too large to be held exactly by
+    /// `Number`, a `0x`/`0o`/`0b` literal or a programmer mode value.
+    Integer(BigInt),
+    /// The result of a comparison, or `true`/`false`.
+    Boolean(bool),
     Pi,
     Euler,
+    /// A named value looked up when the expression is evaluated.
+    Variable {
+        name: String,
+        span: Span,
+    },
     BinaryOp {
         left: Box<ASTNode>,
         op: Token,
         right: Box<ASTNode>,
+        span: Span,
     },
     UnaryOp {
         op: Token,
         operand: Box<ASTNode>,
+        span: Span,
     },
-    Function {
-        func: Token,
-        argument: Box<ASTNode>,
+    Grouping(Box<ASTNode>),
+    /// An angle written with an explicit unit, e.g. `30deg` or `1.2rad`.
+    Angle {
+        value: Box<ASTNode>,
+        unit: AngleMode,
+        span: Span,
     },
-    LogBase {
-        base: Box<ASTNode>,
-        number: Box<ASTNode>,
+    /// A call of a built-in or user-defined function, e.g. `sin(30)`,
+    /// `log(2, 8)` or `f(1000, 0.05)`.
+    Call {
+        name: String,
+        args: Vec<ASTNode>,
+        span: Span,
     },
-    Grouping(Box<ASTNode>),
 }
+
+/// A line of input: an expression, an assignment of one to a variable or
+/// the definition of a function.
+#[derive(Debug, PartialEq)]
+pub enum Statement {
+    Expression(ASTNode),
+    Assignment {
+        name: String,
+        value: ASTNode,
+    },
+    /// `f(x, r) = x * (1 + r)^2`
+    Definition {
+        name: String,
+        parameters: Vec<String>,
+        body: ASTNode,
+    },
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ParseError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl ParseError {
+    fn new(message: &str, span: Span) -> Self {
+        Self {
+            message: message.to_string(),
+            span,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// `not` binds looser than comparisons but tighter than `and`.
+const NOT_PRECEDENCE: u8 = 3;
+const IMPLICIT_MULTIPLICATION_PRECEDENCE: u8 = 11;
+const UNARY_MINUS_PRECEDENCE: u8 = 12;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Associativity {
+    Left,
+    Right,
+}
+
 #[derive(Debug, PartialEq)]
 pub struct Parser {
-    tokens: Vec<Token>,
+    tokens: Vec<SpannedToken>,
     position: usize,
+    /// Arity of every known function, by name.
+    functions: HashMap<String, Arity>,
+    /// Names of the built-in functions, which cannot be redefined.
+    builtins: HashSet<String>,
 }
 
 impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
+    /// A parser that knows the built-in functions.
+    pub fn new(tokens: Vec<SpannedToken>) -> Self {
+        Self::with_functions(tokens, FunctionRegistry::default().arities())
+    }
+    /// A parser that reads `name(...)` as a call of the functions in
+    /// `functions`, which maps each name to its arity. The default
+    /// built-ins among them cannot be redefined.
+    pub fn with_functions(tokens: Vec<SpannedToken>, functions: HashMap<String, Arity>) -> Self {
         Self {
             tokens,
             position: 0,
+            functions,
+            builtins: FunctionRegistry::default().arities().into_keys().collect(),
+        }
+    }
+    /// A parser that knows the built-in and defined functions of
+    /// `environment`.
+    pub fn with_environment(tokens: Vec<SpannedToken>, environment: &Environment) -> Self {
+        Self {
+            builtins: environment.builtins().arities().into_keys().collect(),
+            ..Self::with_functions(tokens, environment.function_arities())
         }
     }
     fn current_token(&self) -> Option<&Token> {
-        self.tokens.get(self.position)
+        self.tokens.get(self.position).map(|t| &t.token)
+    }
+    fn current_span(&self) -> Span {
+        self.tokens
+            .get(self.position)
+            .or(self.tokens.last())
+            .map(|t| t.span)
+            .unwrap_or_default()
+    }
+    /// Span from `start` up to the end of the last consumed token.
+    fn span_from(&self, start: Span) -> Span {
+        match self
+            .position
+            .checked_sub(1)
+            .and_then(|i| self.tokens.get(i))
+        {
+            Some(previous) => start.to(previous.span),
+            None => start,
+        }
     }
     fn next_token(&mut self) -> Option<&Token> {
         self.position += 1;
         //println!("Current token at position {}: {:?}", self.position, self.current_token());
         self.current_token()
     }
-    pub fn parse_expression(&mut self) -> Result<ASTNode, String> {
+    /// Parses `name = expression` as an assignment, `name(a, b) = expression`
+    /// as a function definition and anything else as an expression.
+    pub fn parse_statement(&mut self) -> Result<Statement, ParseError> {
+        if let Some(definition) = self.parse_definition()? {
+            return Ok(definition);
+        }
+        if let [SpannedToken {
+            token: Token::Identifier(name),
+            span,
+        }, SpannedToken {
+            token: Token::Assign,
+            ..
+        }, ..] = &self.tokens[self.position..]
+        {
+            if Environment::is_history(name) {
+                return Err(ParseError::new(
+                    &format!("`{}` is an earlier result and cannot be assigned", name),
+                    *span,
+                ));
+            }
+            // A function name is always read as a call, so the variable
+            // could never be used.
+            if self.functions.contains_key(name) {
+                return Err(ParseError::new(
+                    &format!("`{}` is a function and cannot be assigned", name),
+                    *span,
+                ));
+            }
+            let name = name.clone();
+            self.position += 2;
+            let value = self.parse_expression()?;
+            return Ok(Statement::Assignment { name, value });
+        }
+        Ok(Statement::Expression(self.parse_expression()?))
+    }
+    /// Parses a function definition, or returns `None` without consuming
+    /// anything when the input does not start with `name(a, b) =`.
+    fn parse_definition(&mut self) -> Result<Option<Statement>, ParseError> {
+        let start = self.position;
+        let Some(Token::Identifier(name)) = self.current_token().cloned() else {
+            return Ok(None);
+        };
+        let name_span = self.current_span();
+        let mut parameters: Vec<(String, Span)> = Vec::new();
+        // The last comma, while no parameter has followed it.
+        let mut comma = None;
+        if self.next_token() == Some(&Token::LParen) {
+            while let Some(Token::Identifier(parameter)) = self.next_token().cloned() {
+                parameters.push((parameter, self.current_span()));
+                comma = None;
+                if self.next_token() != Some(&Token::Comma) {
+                    break;
+                }
+                comma = Some(self.current_span());
+            }
+        }
+        if parameters.is_empty()
+            || self.current_token() != Some(&Token::RParen)
+            || self.next_token() != Some(&Token::Assign)
+        {
+            self.position = start;
+            return Ok(None);
+        }
+        self.next_token();
+
+        if Environment::is_history(&name) {
+            return Err(ParseError::new(
+                &format!("`{}` is an earlier result and cannot be defined", name),
+                name_span,
+            ));
+        }
+        if self.builtins.contains(&name) {
+            return Err(ParseError::new(
+                &format!("`{}` is a built-in function and cannot be defined", name),
+                name_span,
+            ));
+        }
+        if let Some(span) = comma {
+            return Err(ParseError::new("Expected a parameter after ','", span));
+        }
+        for (index, (parameter, span)) in parameters.iter().enumerate() {
+            if parameters[..index]
+                .iter()
+                .any(|(other, _)| other == parameter)
+            {
+                return Err(ParseError::new(
+                    &format!("The parameter `{}` appears twice", parameter),
+                    *span,
+                ));
+            }
+        }
+        // Known before the body is parsed, so the function can call itself.
+        self.functions
+            .insert(name.clone(), Arity::Exactly(parameters.len()));
+        let body = self.parse_expression()?;
+        Ok(Some(Statement::Definition {
+            name,
+            parameters: parameters.into_iter().map(|(name, _)| name).collect(),
+            body,
+        }))
+    }
+    pub fn parse_expression(&mut self) -> Result<ASTNode, ParseError> {
         let expr = self.parse_binary_op(0)?;
         if let Some(Token::Eof) = self.current_token() {
             Ok(expr)
         } else {
-            Err("Unexpected input after end of expression".to_string())
+            Err(ParseError::new(
+                "Unexpected input after end of expression",
+                self.current_span(),
+            ))
         }
     }
-    fn parse_inner_expression(&mut self) -> Result<ASTNode, String> {
+    fn parse_inner_expression(&mut self) -> Result<ASTNode, ParseError> {
         self.parse_binary_op(0)
     }
-    fn parse_primary(&mut self) -> Result<ASTNode, String> {
+    /// Parses the postfix operators following a primary: `!`, the angle
+    /// unit suffixes `deg`/`°`, `rad` and `grad`, and `%` when no operand
+    /// follows it, so `10%` is a percent and `10 % 3` a remainder.
+    fn parse_postfix(&mut self, mut node: ASTNode, start: Span) -> ASTNode {
+        loop {
+            let unit = match self.current_token() {
+                Some(Token::Percent) if !self.percent_has_operand() => {
+                    self.next_token();
+                    node = ASTNode::UnaryOp {
+                        op: Token::Percent,
+                        operand: Box::new(node),
+                        span: self.span_from(start),
+                    };
+                    continue;
+                }
+                Some(op @ (Token::Fact | Token::DoubleFact)) => {
+                    let op = op.clone();
+                    self.next_token();
+                    node = ASTNode::UnaryOp {
+                        op,
+                        operand: Box::new(node),
+                        span: self.span_from(start),
+                    };
+                    continue;
+                }
+                Some(Token::Deg) => AngleMode::Degrees,
+                Some(Token::Rad) => AngleMode::Radians,
+                Some(Token::Grad) => AngleMode::Gradians,
+                _ => return node,
+            };
+            self.next_token();
+            node = ASTNode::Angle {
+                value: Box::new(node),
+                unit,
+                span: self.span_from(start),
+            };
+        }
+    }
+    fn parse_primary(&mut self) -> Result<ASTNode, ParseError> {
+        let start = self.current_span();
         if let Some(token) = self.current_token().cloned() {
             match token {
                 Token::Number(value) => {
                     self.next_token();
-                    let mut node = ASTNode::Number(value);
-
-                    if let Some(Token::Fact) = self.current_token() {
-                        self.next_token();
-                        node = ASTNode::UnaryOp {
-                            op: Token::Fact,
-                            operand: Box::new(node),
-                        }
-                    }
-                    Ok(node)
+                    Ok(self.parse_postfix(ASTNode::Number(value), start))
+                }
+                Token::Integer(value) => {
+                    self.next_token();
+                    let node = ASTNode::Integer(BigInt::from_i128(value.into()));
+                    Ok(self.parse_postfix(node, start))
                 }
                 Token::Pi => {
                     self.next_token();
-                    let mut node = ASTNode::Pi;
-                    if let Some(Token::Fact) = self.current_token() {
-                        self.next_token();
-                        node = ASTNode::UnaryOp {
-                            op: Token::Fact,
-                            operand: Box::new(node),
-                        };
-                    }
-                    Ok(node)
+                    Ok(self.parse_postfix(ASTNode::Pi, start))
+                }
+                Token::Boolean(value) => {
+                    self.next_token();
+                    Ok(ASTNode::Boolean(value))
+                }
+                Token::Not => {
+                    self.next_token();
+                    let operand = self.parse_binary_op(NOT_PRECEDENCE)?;
+                    Ok(ASTNode::UnaryOp {
+                        op: Token::Not,
+                        operand: Box::new(operand),
+                        span: self.span_from(start),
+                    })
+                }
+                Token::Identifier(name) => {
+                    self.next_token();
+                    let node = match self.functions.get(&name) {
+                        Some(&arity) => self.parse_call(name, arity, start)?,
+                        None => ASTNode::Variable { name, span: start },
+                    };
+                    Ok(self.parse_postfix(node, start))
                 }
                 Token::Euler => {
                     self.next_token();
-                    let mut node = ASTNode::Euler;
-                    if let Some(Token::Fact) = self.current_token() {
-                        self.next_token();
-                        node = ASTNode::UnaryOp {
-                            op: Token::Fact,
-                            operand: Box::new(node),
-                        };
-                    }
-                    Ok(node)
+                    Ok(self.parse_postfix(ASTNode::Euler, start))
                 }
-                Token::Minus => {
+                Token::Minus | Token::BitNot => {
                     self.next_token();
-                    let operand = self.parse_primary()?;
+                    // Unary minus binds looser than `^` and `!`, so -2^2 is
+                    // -(2^2); `~` binds the same way.
+                    let operand = self.parse_binary_op(UNARY_MINUS_PRECEDENCE)?;
                     Ok(ASTNode::UnaryOp {
-                        op: Token::Minus,
+                        op: token,
                         operand: Box::new(operand),
+                        span: self.span_from(start),
                     })
                 }
-                Token::LParen => {
+                Token::Fact => {
                     self.next_token();
-                    let expr = self.parse_inner_expression()?;
-                    if let Some(Token::RParen) = self.current_token() {
-                        self.next_token();
-                        let mut node = ASTNode::Grouping(Box::new(expr));
-
-                        if let Some(Token::Fact) = self.current_token() {
-                            self.next_token();
-                            node = ASTNode::UnaryOp {
-                                op: Token::Fact,
-                                operand: Box::new(node),
-                            };
-                        }
-
-                        Ok(node)
-                    } else {
-                        Err("Expected right parenthesis".to_string())
-                    }
+                    // A prefix `!` is the subfactorial: !4 = 9.
+                    let operand = self.parse_primary()?;
+                    Ok(ASTNode::UnaryOp {
+                        op: Token::Subfact,
+                        operand: Box::new(operand),
+                        span: self.span_from(start),
+                    })
                 }
-                Token::Log => {
+                Token::Sqrt => {
                     self.next_token();
-                    if let Some(Token::LParen) = self.current_token() {
-                        self.next_token();
-                    } else {
-                        return Err("Expected '(' after log function".to_string());
-                    }
-
-                    let first_arg = self.parse_inner_expression()?;
-
-                    let base;
-                    let number;
-
-                    if let Some(Token::Comma) = self.current_token() {
-                        self.next_token();
-                        base = first_arg;
-                        number = self.parse_inner_expression()?;
-                    } else {
-                        base = ASTNode::Number(2.0);
-                        number = first_arg;
-                    }
-
-                    if let Some(Token::RParen) = self.current_token() {
-                        self.next_token();
-                    } else {
-                        return Err(
-                            "After the log function arguments there should be ')'".to_string()
-                        );
-                    }
-                    let mut node = ASTNode::LogBase {
-                        base: Box::new(base),
-                        number: Box::new(number),
-                    };
-                    if let Some(Token::Fact) = self.current_token() {
-                        self.next_token();
-                        node = ASTNode::UnaryOp {
-                            op: Token::Fact,
-                            operand: Box::new(node),
-                        };
-                    }
-                    Ok(node)
+                    // Like the prefix `!`, `√` takes the primary after it:
+                    // √9 + 1 is sqrt(9) + 1 and √x² is (sqrt(x))².
+                    let operand = self.parse_primary()?;
+                    Ok(ASTNode::Call {
+                        name: "sqrt".to_string(),
+                        args: vec![operand],
+                        span: self.span_from(start),
+                    })
                 }
-                Token::Sin
-                | Token::Cos
-                | Token::Tg
-                | Token::Cotg
-                | Token::Ln
-                | Token::Sqrt
-                | Token::Abs
-                | Token::Sec
-                | Token::Csc
-                | Token::Asin
-                | Token::Acos
-                | Token::Atg
-                | Token::Actg => {
-                    let func = token;
+                Token::LParen => {
                     self.next_token();
-
-                    if let Some(Token::LParen) = self.current_token() {
+                    let expr = self.parse_inner_expression()?;
+                    if let Some(Token::RParen) = self.current_token() {
                         self.next_token();
-                        let argument = self.parse_inner_expression()?;
-
-                        if let Some(Token::RParen) = self.current_token() {
-                            self.next_token();
-                            let mut node = ASTNode::Function {
-                                func,
-                                argument: Box::new(argument),
-                            };
-                            if let Some(Token::Fact) = self.current_token() {
-                                self.next_token();
-                                node = ASTNode::UnaryOp {
-                                    op: Token::Fact,
-                                    operand: Box::new(node),
-                                };
-                            }
-
-                            Ok(node)
-                        } else {
-                            Err("Expected right parenthesis after function argument".to_string())
-                        }
+                        let node = ASTNode::Grouping(Box::new(expr));
+                        Ok(self.parse_postfix(node, start))
                     } else {
-                        Err("Expected '(' after function name".to_string())
+                        Err(ParseError::new(
+                            "Expected right parenthesis",
+                            start.to(self.current_span()),
+                        ))
                     }
                 }
-                _ => Err("Unexpected token".to_string()),
+                Token::Eof => Err(ParseError::new("Unexpected end of input", start)),
+                _ => Err(ParseError::new("Unexpected token", start)),
             }
         } else {
-            Err("Unexpected end of input".to_string())
+            Err(ParseError::new("Unexpected end of input", start))
+        }
+    }
+    /// Parses the arguments of a function call, after the function name.
+    fn parse_call(
+        &mut self,
+        name: String,
+        arity: Arity,
+        start: Span,
+    ) -> Result<ASTNode, ParseError> {
+        if self.current_token() != Some(&Token::LParen) {
+            return Err(ParseError::new(
+                &format!("`{}` is a function; call it as {}(...)", name, name),
+                start,
+            ));
+        }
+        let mut args = Vec::new();
+        loop {
+            self.next_token();
+            args.push(self.parse_inner_expression()?);
+            match self.current_token() {
+                Some(Token::Comma) => {}
+                Some(Token::RParen) => break,
+                _ => {
+                    return Err(ParseError::new(
+                        "Expected ',' or ')' after function argument",
+                        start.to(self.current_span()),
+                    ))
+                }
+            }
+        }
+        self.next_token();
+        let span = self.span_from(start);
+        if !arity.accepts(args.len()) {
+            return Err(ParseError::new(
+                &format!("`{}` takes {}, got {}", name, arity, args.len()),
+                span,
+            ));
         }
+        Ok(ASTNode::Call { name, args, span })
     }
-    fn get_precedence(op: &Token) -> u8 {
+    pub fn get_precedence(op: &Token) -> u8 {
         match op {
-            Token::Plus | Token::Minus => 1,
-            Token::Multiply | Token::Divide => 2,
-            Token::Exponent => 3,
+            Token::Or => 1,
+            Token::And => 2,
+            // Comparisons bind looser than any arithmetic or bitwise
+            // operator: `x & 1 == 0` is (x & 1) == 0.
+            Token::Equal
+            | Token::NotEqual
+            | Token::Less
+            | Token::LessEqual
+            | Token::Greater
+            | Token::GreaterEqual => 4,
+            // Bitwise operators bind looser than arithmetic, as in C:
+            // `1 + 2 << 3` is (1 + 2) << 3.
+            Token::BitOr => 5,
+            Token::BitXor => 6,
+            Token::BitAnd => 7,
+            Token::ShiftLeft | Token::ShiftRight => 8,
+            Token::Plus | Token::Minus => 9,
+            Token::Multiply | Token::Divide | Token::IntDivide | Token::Modulo | Token::Percent => {
+                10
+            }
+            Token::Exponent => 12,
             _ => 0,
         }
     }
-    fn parse_binary_op(&mut self, min_precedence: u8) -> Result<ASTNode, String> {
+    pub fn get_associativity(op: &Token) -> Associativity {
+        match op {
+            Token::Exponent => Associativity::Right,
+            _ => Associativity::Left,
+        }
+    }
+    /// Tokens that can follow an operand directly and are then read as an
+    /// implicit multiplication: `2pi`, `3(4 + 5)`, `(1 + 2)(3 + 4)`,
+    /// `2sin(30)`, `2√3`. A number literal never starts an implicit factor, so
+    /// `2 3` is still rejected.
+    fn starts_implicit_factor(token: &Token) -> bool {
+        matches!(
+            token,
+            Token::Pi | Token::Euler | Token::Identifier(_) | Token::Sqrt | Token::LParen
+        )
+    }
+    /// Whether the `%` at the current position has an operand after it,
+    /// which makes it a remainder. A `-` only starts that operand when it is
+    /// written against the operand but apart from the `%`: `7 % -3` is a
+    /// remainder, while `10% - 5` and `10%-5` subtract from a percent.
+    fn percent_has_operand(&self) -> bool {
+        let [percent, next, rest @ ..] = &self.tokens[self.position..] else {
+            return false;
+        };
+        Parser::starts_operand(&next.token)
+            || (next.token == Token::Minus
+                && next.span.start > percent.span.end
+                && rest.first().is_some_and(|operand| {
+                    operand.span.start == next.span.end && Parser::starts_operand(&operand.token)
+                }))
+    }
+    /// Tokens that begin an operand. A `-` is not among them; see
+    /// `percent_has_operand`.
+    fn starts_operand(token: &Token) -> bool {
+        Parser::starts_implicit_factor(token)
+            || matches!(
+                token,
+                Token::Number(_)
+                    | Token::Integer(_)
+                    | Token::Boolean(_)
+                    | Token::BitNot
+                    | Token::Not
+                    | Token::Fact
+            )
+    }
+    fn parse_binary_op(&mut self, min_precedence: u8) -> Result<ASTNode, ParseError> {
+        let start = self.current_span();
         let mut left = self.parse_primary()?;
-        while let Some(op) = self.current_token() {
-            if op == &Token::Eof || op == &Token::RParen || op == &Token::Comma {
+        while let Some(token) = self.current_token().cloned() {
+            // Juxtaposition binds tighter than `*` and `/` but looser than
+            // `^`: `1/2pi` is 1 / (2 * pi) and `2^3pi` is (2 ^ 3) * pi.
+            let implicit = Parser::starts_implicit_factor(&token);
+            let (op, precedence) = if implicit {
+                (Token::Multiply, IMPLICIT_MULTIPLICATION_PRECEDENCE)
+            } else {
+                let precedence = Parser::get_precedence(&token);
+                // A `%` left by `parse_postfix` has an operand after it.
+                let op = if token == Token::Percent {
+                    Token::Modulo
+                } else {
+                    token
+                };
+                (op, precedence)
+            };
+            if precedence == 0 || precedence < min_precedence {
                 break;
             }
 
-            let precedence = Parser::get_precedence(op);
-            if precedence < min_precedence {
-                break;
+            if !implicit {
+                self.next_token();
             }
 
-            let op = *self.current_token().unwrap();
-            self.next_token();
-
-            let right = self.parse_binary_op(precedence + 1)?;
+            let next_precedence = match Parser::get_associativity(&op) {
+                Associativity::Left => precedence + 1,
+                Associativity::Right => precedence,
+            };
+            let right = self.parse_binary_op(next_precedence)?;
             left = ASTNode::BinaryOp {
                 left: Box::new(left),
                 op,
                 right: Box::new(right),
+                span: self.span_from(start),
             };
         }
         Ok(left)
@@ -251,12 +563,11 @@ impl Parser {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::lexer::{Lexer, Token};
+    use crate::lexer::{Lexer, Span, SpannedToken, Token};
 
-    fn lex_input(input: &str) -> Vec<Token> {
+    fn lex_input(input: &str) -> Vec<SpannedToken> {
         let mut lexer = Lexer::new();
-        lexer.tokenize(input);
-        lexer.tokens
+        lexer.tokenize(input).unwrap()
     }
 
     #[test]
@@ -271,6 +582,7 @@ mod tests {
                 left: Box::new(ASTNode::Number(2.0)),
                 op: Token::Plus,
                 right: Box::new(ASTNode::Number(3.0)),
+                span: Span::new(0, 5),
             }
         );
     }
@@ -287,6 +599,7 @@ mod tests {
                 left: Box::new(ASTNode::Pi),
                 op: Token::Plus,
                 right: Box::new(ASTNode::Euler),
+                span: Span::new(0, 6),
             }
         );
     }
@@ -304,7 +617,9 @@ mod tests {
                 operand: Box::new(ASTNode::UnaryOp {
                     op: Token::Fact,
                     operand: Box::new(ASTNode::Number(5.0)),
+                    span: Span::new(1, 3),
                 }),
+                span: Span::new(0, 3),
             }
         );
     }
@@ -317,9 +632,10 @@ mod tests {
 
         assert_eq!(
             ast,
-            ASTNode::Function {
-                func: Token::Sin,
-                argument: Box::new(ASTNode::Pi),
+            ASTNode::Call {
+                name: "sin".to_string(),
+                args: vec![ASTNode::Pi],
+                span: Span::new(0, 7),
             }
         );
     }
@@ -337,9 +653,11 @@ mod tests {
                     left: Box::new(ASTNode::Number(2.0)),
                     op: Token::Plus,
                     right: Box::new(ASTNode::Number(3.0)),
+                    span: Span::new(1, 6),
                 }))),
                 op: Token::Multiply,
                 right: Box::new(ASTNode::Number(4.0)),
+                span: Span::new(0, 11),
             }
         );
     }
@@ -356,24 +674,30 @@ mod tests {
                 left: Box::new(ASTNode::BinaryOp {
                     left: Box::new(ASTNode::Number(3.0)),
                     op: Token::Plus,
-                    right: Box::new(ASTNode::Function {
-                        func: Token::Sin,
-                        argument: Box::new(ASTNode::BinaryOp {
+                    right: Box::new(ASTNode::Call {
+                        name: "sin".to_string(),
+                        args: vec![ASTNode::BinaryOp {
                             left: Box::new(ASTNode::Number(2.0)),
                             op: Token::Multiply,
                             right: Box::new(ASTNode::Pi),
-                        }),
+                            span: Span::new(8, 14),
+                        }],
+                        span: Span::new(4, 15),
                     }),
+                    span: Span::new(0, 15),
                 }),
                 op: Token::Minus,
                 right: Box::new(ASTNode::BinaryOp {
-                    left: Box::new(ASTNode::LogBase {
-                        base: Box::new(ASTNode::Number(2.0)),
-                        number: Box::new(ASTNode::Number(10.0)),
+                    left: Box::new(ASTNode::Call {
+                        name: "log".to_string(),
+                        args: vec![ASTNode::Number(2.0), ASTNode::Number(10.0)],
+                        span: Span::new(18, 27),
                     }),
                     op: Token::Exponent,
                     right: Box::new(ASTNode::Number(2.0)),
+                    span: Span::new(18, 31),
                 }),
+                span: Span::new(0, 31),
             }
         );
     }
@@ -387,16 +711,571 @@ mod tests {
         assert_eq!(
             ast,
             ASTNode::BinaryOp {
-                left: Box::new(ASTNode::Function {
-                    func: Token::Sin,
-                    argument: Box::new(ASTNode::Number(30.0))
+                left: Box::new(ASTNode::Call {
+                    name: "sin".to_string(),
+                    args: vec![ASTNode::Number(30.0)],
+                    span: Span::new(0, 7),
                 }),
                 op: Token::Plus,
                 right: Box::new(ASTNode::UnaryOp {
                     op: Token::Fact,
-                    operand: Box::new(ASTNode::Number(4.0))
+                    operand: Box::new(ASTNode::Number(4.0)),
+                    span: Span::new(10, 12),
+                }),
+                span: Span::new(0, 12),
+            }
+        );
+    }
+
+    #[test]
+    fn check_double_and_subfactorial() {
+        let tokens = lex_input("7!! - !4");
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse_expression().unwrap();
+
+        assert_eq!(
+            ast,
+            ASTNode::BinaryOp {
+                left: Box::new(ASTNode::UnaryOp {
+                    op: Token::DoubleFact,
+                    operand: Box::new(ASTNode::Number(7.0)),
+                    span: Span::new(0, 3),
+                }),
+                op: Token::Minus,
+                right: Box::new(ASTNode::UnaryOp {
+                    op: Token::Subfact,
+                    operand: Box::new(ASTNode::Number(4.0)),
+                    span: Span::new(6, 8),
+                }),
+                span: Span::new(0, 8),
+            }
+        );
+    }
+
+    #[test]
+    fn check_error_spans() {
+        let tokens = lex_input("(2 + 3 * 4");
+        let mut parser = Parser::new(tokens);
+        let error = parser.parse_expression().unwrap_err();
+
+        assert_eq!(error.message, "Expected right parenthesis");
+        assert_eq!(error.span, Span::new(0, 10));
+
+        let tokens = lex_input("2 + * 3");
+        let mut parser = Parser::new(tokens);
+        let error = parser.parse_expression().unwrap_err();
+
+        assert_eq!(error.message, "Unexpected token");
+        assert_eq!(error.span, Span::new(4, 5));
+
+        // `e3` apart from a number is e followed by 3, not an exponent.
+        let tokens = lex_input("2 e3");
+        let mut parser = Parser::new(tokens);
+        let error = parser.parse_expression().unwrap_err();
+
+        assert_eq!(error.message, "Unexpected input after end of expression");
+        assert_eq!(error.span, Span::new(3, 4));
+    }
+
+    #[test]
+    fn check_implicit_multiplication() {
+        let tokens = lex_input("2pi");
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse_expression().unwrap();
+
+        assert_eq!(
+            ast,
+            ASTNode::BinaryOp {
+                left: Box::new(ASTNode::Number(2.0)),
+                op: Token::Multiply,
+                right: Box::new(ASTNode::Pi),
+                span: Span::new(0, 3),
+            }
+        );
+
+        let tokens = lex_input("(1+2)(3+4)");
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse_expression().unwrap();
+
+        assert_eq!(
+            ast,
+            ASTNode::BinaryOp {
+                left: Box::new(ASTNode::Grouping(Box::new(ASTNode::BinaryOp {
+                    left: Box::new(ASTNode::Number(1.0)),
+                    op: Token::Plus,
+                    right: Box::new(ASTNode::Number(2.0)),
+                    span: Span::new(1, 4),
+                }))),
+                op: Token::Multiply,
+                right: Box::new(ASTNode::Grouping(Box::new(ASTNode::BinaryOp {
+                    left: Box::new(ASTNode::Number(3.0)),
+                    op: Token::Plus,
+                    right: Box::new(ASTNode::Number(4.0)),
+                    span: Span::new(6, 9),
+                }))),
+                span: Span::new(0, 10),
+            }
+        );
+
+        let tokens = lex_input("2sin(30)");
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse_expression().unwrap();
+
+        assert_eq!(
+            ast,
+            ASTNode::BinaryOp {
+                left: Box::new(ASTNode::Number(2.0)),
+                op: Token::Multiply,
+                right: Box::new(ASTNode::Call {
+                    name: "sin".to_string(),
+                    args: vec![ASTNode::Number(30.0)],
+                    span: Span::new(1, 8),
                 }),
+                span: Span::new(0, 8),
             }
         );
     }
+
+    #[test]
+    fn check_implicit_multiplication_precedence() {
+        let tokens = lex_input("1/2pi");
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse_expression().unwrap();
+
+        assert_eq!(
+            ast,
+            ASTNode::BinaryOp {
+                left: Box::new(ASTNode::Number(1.0)),
+                op: Token::Divide,
+                right: Box::new(ASTNode::BinaryOp {
+                    left: Box::new(ASTNode::Number(2.0)),
+                    op: Token::Multiply,
+                    right: Box::new(ASTNode::Pi),
+                    span: Span::new(2, 5),
+                }),
+                span: Span::new(0, 5),
+            }
+        );
+
+        let tokens = lex_input("2^3pi");
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse_expression().unwrap();
+
+        assert_eq!(
+            ast,
+            ASTNode::BinaryOp {
+                left: Box::new(ASTNode::BinaryOp {
+                    left: Box::new(ASTNode::Number(2.0)),
+                    op: Token::Exponent,
+                    right: Box::new(ASTNode::Number(3.0)),
+                    span: Span::new(0, 3),
+                }),
+                op: Token::Multiply,
+                right: Box::new(ASTNode::Pi),
+                span: Span::new(0, 5),
+            }
+        );
+
+        let tokens = lex_input("2 3");
+        let mut parser = Parser::new(tokens);
+        let error = parser.parse_expression().unwrap_err();
+
+        assert_eq!(error.message, "Unexpected input after end of expression");
+        assert_eq!(error.span, Span::new(2, 3));
+    }
+
+    #[test]
+    fn check_right_associative_exponent() {
+        let tokens = lex_input("2 ^ 3 ^ 2");
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse_expression().unwrap();
+
+        assert_eq!(
+            ast,
+            ASTNode::BinaryOp {
+                left: Box::new(ASTNode::Number(2.0)),
+                op: Token::Exponent,
+                right: Box::new(ASTNode::BinaryOp {
+                    left: Box::new(ASTNode::Number(3.0)),
+                    op: Token::Exponent,
+                    right: Box::new(ASTNode::Number(2.0)),
+                    span: Span::new(4, 9),
+                }),
+                span: Span::new(0, 9),
+            }
+        );
+    }
+
+    #[test]
+    fn check_left_associative_operators() {
+        let tokens = lex_input("8 / 4 / 2");
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse_expression().unwrap();
+
+        assert_eq!(
+            ast,
+            ASTNode::BinaryOp {
+                left: Box::new(ASTNode::BinaryOp {
+                    left: Box::new(ASTNode::Number(8.0)),
+                    op: Token::Divide,
+                    right: Box::new(ASTNode::Number(4.0)),
+                    span: Span::new(0, 5),
+                }),
+                op: Token::Divide,
+                right: Box::new(ASTNode::Number(2.0)),
+                span: Span::new(0, 9),
+            }
+        );
+    }
+
+    #[test]
+    fn check_unary_minus_precedence() {
+        let tokens = lex_input("-2^2");
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse_expression().unwrap();
+
+        assert_eq!(
+            ast,
+            ASTNode::UnaryOp {
+                op: Token::Minus,
+                operand: Box::new(ASTNode::BinaryOp {
+                    left: Box::new(ASTNode::Number(2.0)),
+                    op: Token::Exponent,
+                    right: Box::new(ASTNode::Number(2.0)),
+                    span: Span::new(1, 4),
+                }),
+                span: Span::new(0, 4),
+            }
+        );
+
+        let tokens = lex_input("2^-1 * 3");
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse_expression().unwrap();
+
+        assert_eq!(
+            ast,
+            ASTNode::BinaryOp {
+                left: Box::new(ASTNode::BinaryOp {
+                    left: Box::new(ASTNode::Number(2.0)),
+                    op: Token::Exponent,
+                    right: Box::new(ASTNode::UnaryOp {
+                        op: Token::Minus,
+                        operand: Box::new(ASTNode::Number(1.0)),
+                        span: Span::new(2, 4),
+                    }),
+                    span: Span::new(0, 4),
+                }),
+                op: Token::Multiply,
+                right: Box::new(ASTNode::Number(3.0)),
+                span: Span::new(0, 8),
+            }
+        );
+
+        let tokens = lex_input("-2 + 3");
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse_expression().unwrap();
+
+        assert_eq!(
+            ast,
+            ASTNode::BinaryOp {
+                left: Box::new(ASTNode::UnaryOp {
+                    op: Token::Minus,
+                    operand: Box::new(ASTNode::Number(2.0)),
+                    span: Span::new(0, 2),
+                }),
+                op: Token::Plus,
+                right: Box::new(ASTNode::Number(3.0)),
+                span: Span::new(0, 6),
+            }
+        );
+    }
+
+    #[test]
+    fn check_angle_suffix() {
+        let tokens = lex_input("sin(30deg)");
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse_expression().unwrap();
+
+        assert_eq!(
+            ast,
+            ASTNode::Call {
+                name: "sin".to_string(),
+                args: vec![ASTNode::Angle {
+                    value: Box::new(ASTNode::Number(30.0)),
+                    unit: AngleMode::Degrees,
+                    span: Span::new(4, 9),
+                }],
+                span: Span::new(0, 10),
+            }
+        );
+
+        let tokens = lex_input("2 * 1.5rad");
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse_expression().unwrap();
+
+        assert_eq!(
+            ast,
+            ASTNode::BinaryOp {
+                left: Box::new(ASTNode::Number(2.0)),
+                op: Token::Multiply,
+                right: Box::new(ASTNode::Angle {
+                    value: Box::new(ASTNode::Number(1.5)),
+                    unit: AngleMode::Radians,
+                    span: Span::new(4, 10),
+                }),
+                span: Span::new(0, 10),
+            }
+        );
+    }
+
+    #[test]
+    fn check_assignment() {
+        let tokens = lex_input("x = rate_2");
+        let mut parser = Parser::new(tokens);
+        let statement = parser.parse_statement().unwrap();
+
+        assert_eq!(
+            statement,
+            Statement::Assignment {
+                name: "x".to_string(),
+                value: ASTNode::Variable {
+                    name: "rate_2".to_string(),
+                    span: Span::new(4, 10),
+                },
+            }
+        );
+
+        let tokens = lex_input("ans2 = 1");
+        let mut parser = Parser::new(tokens);
+        let err = parser.parse_statement().unwrap_err();
+        assert_eq!(err.span, Span::new(0, 4));
+
+        let tokens = lex_input("sin = 5");
+        let mut parser = Parser::new(tokens);
+        let err = parser.parse_statement().unwrap_err();
+        assert_eq!(err.message, "`sin` is a function and cannot be assigned");
+        assert_eq!(err.span, Span::new(0, 3));
+
+        let functions = HashMap::from([("f".to_string(), Arity::Exactly(1))]);
+        let mut parser = Parser::with_functions(lex_input("f = 2"), functions);
+        assert!(parser.parse_statement().is_err());
+    }
+
+    #[test]
+    fn check_function_definition_and_call() {
+        let tokens = lex_input("f(x, r) = x * r");
+        let mut parser = Parser::new(tokens);
+        let statement = parser.parse_statement().unwrap();
+
+        let variable = |name: &str, start, end| ASTNode::Variable {
+            name: name.to_string(),
+            span: Span::new(start, end),
+        };
+        assert_eq!(
+            statement,
+            Statement::Definition {
+                name: "f".to_string(),
+                parameters: vec!["x".to_string(), "r".to_string()],
+                body: ASTNode::BinaryOp {
+                    left: Box::new(variable("x", 10, 11)),
+                    op: Token::Multiply,
+                    right: Box::new(variable("r", 14, 15)),
+                    span: Span::new(10, 15),
+                },
+            }
+        );
+
+        let functions = HashMap::from([("f".to_string(), Arity::Exactly(2))]);
+        let tokens = lex_input("f(1, x)");
+        let mut parser = Parser::with_functions(tokens, functions.clone());
+        assert_eq!(
+            parser.parse_statement().unwrap(),
+            Statement::Expression(ASTNode::Call {
+                name: "f".to_string(),
+                args: vec![ASTNode::Number(1.0), variable("x", 5, 6)],
+                span: Span::new(0, 7),
+            })
+        );
+
+        let tokens = lex_input("2 + f(1)");
+        let mut parser = Parser::with_functions(tokens, functions.clone());
+        let err = parser.parse_statement().unwrap_err();
+        assert_eq!(err.message, "`f` takes 2 arguments, got 1");
+        assert_eq!(err.span, Span::new(4, 8));
+
+        let tokens = lex_input("sin(x) = x");
+        let mut parser = Parser::new(tokens);
+        let err = parser.parse_statement().unwrap_err();
+        assert_eq!(
+            err.message,
+            "`sin` is a built-in function and cannot be defined"
+        );
+        assert_eq!(err.span, Span::new(0, 3));
+        assert_eq!(parser.functions.get("sin"), Some(&Arity::Exactly(1)));
+
+        // A user function can be redefined.
+        let mut parser = Parser::with_functions(lex_input("f(x) = x"), functions.clone());
+        assert!(parser.parse_statement().is_ok());
+
+        let tokens = lex_input("f(x,) = x");
+        let mut parser = Parser::new(tokens);
+        let err = parser.parse_statement().unwrap_err();
+        assert_eq!(err.message, "Expected a parameter after ','");
+        assert_eq!(err.span, Span::new(3, 4));
+
+        // Without a definition, `x(1 + 2)` is an implicit multiplication.
+        let tokens = lex_input("x(1 + 2) = 3");
+        let mut parser = Parser::new(tokens);
+        assert!(parser.parse_statement().is_err());
+    }
+
+    #[test]
+    fn check_square_root_sign() {
+        // `√` takes the primary after it, and a superscript is a power.
+        let tokens = lex_input("2√9²");
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse_expression().unwrap();
+
+        assert_eq!(
+            ast,
+            ASTNode::BinaryOp {
+                left: Box::new(ASTNode::Number(2.0)),
+                op: Token::Multiply,
+                right: Box::new(ASTNode::BinaryOp {
+                    left: Box::new(ASTNode::Call {
+                        name: "sqrt".to_string(),
+                        args: vec![ASTNode::Number(9.0)],
+                        span: Span::new(1, 5),
+                    }),
+                    op: Token::Exponent,
+                    right: Box::new(ASTNode::Number(2.0)),
+                    span: Span::new(1, 7),
+                }),
+                span: Span::new(0, 7),
+            }
+        );
+    }
+
+    #[test]
+    fn check_bitwise_precedence() {
+        // ~1 + 2 << 3 & 0xff | 4 xor 5
+        let tokens = lex_input("~1 + 2 << 3 & 0xff | 4 xor 5");
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse_expression().unwrap();
+
+        let node = |left, op, right, start, end| ASTNode::BinaryOp {
+            left: Box::new(left),
+            op,
+            right: Box::new(right),
+            span: Span::new(start, end),
+        };
+        let not_one = ASTNode::UnaryOp {
+            op: Token::BitNot,
+            operand: Box::new(ASTNode::Number(1.0)),
+            span: Span::new(0, 2),
+        };
+        let sum = node(not_one, Token::Plus, ASTNode::Number(2.0), 0, 6);
+        let shift = node(sum, Token::ShiftLeft, ASTNode::Number(3.0), 0, 11);
+        let mask = ASTNode::Integer(BigInt::from_i128(255));
+        let and = node(shift, Token::BitAnd, mask, 0, 18);
+        let xor = node(
+            ASTNode::Number(4.0),
+            Token::BitXor,
+            ASTNode::Number(5.0),
+            21,
+            28,
+        );
+        assert_eq!(ast, node(and, Token::BitOr, xor, 0, 28));
+    }
+
+    #[test]
+    fn check_percent_and_remainder() {
+        let tokens = lex_input("200 + 10% - 7 % 3");
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse_expression().unwrap();
+
+        let percent = ASTNode::UnaryOp {
+            op: Token::Percent,
+            operand: Box::new(ASTNode::Number(10.0)),
+            span: Span::new(6, 9),
+        };
+        let remainder = ASTNode::BinaryOp {
+            left: Box::new(ASTNode::Number(7.0)),
+            op: Token::Modulo,
+            right: Box::new(ASTNode::Number(3.0)),
+            span: Span::new(12, 17),
+        };
+        assert_eq!(
+            ast,
+            ASTNode::BinaryOp {
+                left: Box::new(ASTNode::BinaryOp {
+                    left: Box::new(ASTNode::Number(200.0)),
+                    op: Token::Plus,
+                    right: Box::new(percent),
+                    span: Span::new(0, 9),
+                }),
+                op: Token::Minus,
+                right: Box::new(remainder),
+                span: Span::new(0, 17),
+            }
+        );
+
+        // A sign against the divisor makes a remainder, a detached one a
+        // subtraction.
+        let mut parser = Parser::new(lex_input("7 % -3"));
+        let ast = parser.parse_expression().unwrap();
+        assert!(matches!(
+            ast,
+            ASTNode::BinaryOp {
+                op: Token::Modulo,
+                ..
+            }
+        ));
+        for input in ["10% - 5", "10%-5"] {
+            let mut parser = Parser::new(lex_input(input));
+            let ast = parser.parse_expression().unwrap();
+            assert!(matches!(
+                ast,
+                ASTNode::BinaryOp {
+                    op: Token::Minus,
+                    ..
+                }
+            ));
+        }
+    }
+
+    #[test]
+    fn check_comparisons_and_logic() {
+        // not 1 + 1 == 2 and x < 3 or false
+        let tokens = lex_input("not 1 + 1 == 2 and x < 3 or false");
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse_expression().unwrap();
+
+        let node = |left, op, right, start, end| ASTNode::BinaryOp {
+            left: Box::new(left),
+            op,
+            right: Box::new(right),
+            span: Span::new(start, end),
+        };
+        let sum = node(
+            ASTNode::Number(1.0),
+            Token::Plus,
+            ASTNode::Number(1.0),
+            4,
+            9,
+        );
+        let equal = node(sum, Token::Equal, ASTNode::Number(2.0), 4, 14);
+        let not = ASTNode::UnaryOp {
+            op: Token::Not,
+            operand: Box::new(equal),
+            span: Span::new(0, 14),
+        };
+        let x = ASTNode::Variable {
+            name: "x".to_string(),
+            span: Span::new(19, 20),
+        };
+        let less = node(x, Token::Less, ASTNode::Number(3.0), 19, 24);
+        let and = node(not, Token::And, less, 0, 24);
+        assert_eq!(ast, node(and, Token::Or, ASTNode::Boolean(false), 0, 33));
+    }
 }
@@ -0,0 +1,245 @@
+use std::fmt;
+
+/// An exact fraction. It is deliberately not reduced automatically, so the
+/// evaluator can show `3/6` before reducing it to `1/2` as its own step.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Rational {
+    numerator: i128,
+    denominator: i128,
+}
+
+fn gcd(mut a: i128, mut b: i128) -> i128 {
+    a = a.abs();
+    b = b.abs();
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+impl Rational {
+    /// Returns `None` for a zero denominator. The sign is moved to the
+    /// numerator.
+    pub fn new(numerator: i128, denominator: i128) -> Option<Self> {
+        if denominator == 0 {
+            return None;
+        }
+        let sign = denominator.signum();
+        Some(Self {
+            numerator: numerator.checked_mul(sign)?,
+            denominator: denominator.checked_mul(sign)?,
+        })
+    }
+
+    pub fn from_integer(value: i128) -> Self {
+        Self {
+            numerator: value,
+            denominator: 1,
+        }
+    }
+
+    /// Converts a finite float to the fraction its shortest decimal form
+    /// denotes, so `0.1` becomes exactly `1/10`.
+    pub fn from_f64(value: f64) -> Option<Self> {
+        if !value.is_finite() {
+            return None;
+        }
+        let decimal = format!("{}", value);
+        let (integer_part, fraction_part) = decimal.split_once('.').unwrap_or((&decimal, ""));
+        let digits = format!("{}{}", integer_part, fraction_part);
+        let numerator = digits.parse::<i128>().ok()?;
+        let denominator = 10i128.checked_pow(fraction_part.len() as u32)?;
+        Some(Self::new(numerator, denominator)?.reduced())
+    }
+
+    /// The fraction with a denominator of at most `max_denominator` that
+    /// matches `value` up to float rounding, found by continued fractions.
+    /// `0.333...` becomes `1/3`; `None` if no such fraction exists.
+    pub fn approximate(value: f64, max_denominator: i128) -> Option<Self> {
+        if !value.is_finite() {
+            return None;
+        }
+        // The last two convergents h/k, starting from 0/1 and 1/0.
+        let (mut h0, mut h1) = (0i128, 1i128);
+        let (mut k0, mut k1) = (1i128, 0i128);
+        let mut x = value;
+        loop {
+            let term = x.floor();
+            if term.abs() > 1e15 {
+                return None;
+            }
+            let h = (term as i128).checked_mul(h1)?.checked_add(h0)?;
+            let k = (term as i128).checked_mul(k1)?.checked_add(k0)?;
+            if k > max_denominator {
+                return None;
+            }
+            (h0, h1, k0, k1) = (h1, h, k1, k);
+            if (h as f64 / k as f64 - value).abs() <= 1e-12 * value.abs().max(1.0) {
+                return Self::new(h, k);
+            }
+            x = 1.0 / (x - term);
+        }
+    }
+
+    pub fn numerator(&self) -> i128 {
+        self.numerator
+    }
+
+    pub fn denominator(&self) -> i128 {
+        self.denominator
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.denominator == 1
+    }
+
+    pub fn is_reduced(&self) -> bool {
+        gcd(self.numerator, self.denominator) <= 1
+    }
+
+    pub fn reduced(&self) -> Self {
+        let divisor = gcd(self.numerator, self.denominator).max(1);
+        Self {
+            numerator: self.numerator / divisor,
+            denominator: self.denominator / divisor,
+        }
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.numerator as f64 / self.denominator as f64
+    }
+
+    /// The same value written over `denominator`, which must be a multiple
+    /// of the current denominator.
+    pub fn expanded_to(&self, denominator: i128) -> Option<Self> {
+        let factor = denominator / self.denominator;
+        Some(Self {
+            numerator: self.numerator.checked_mul(factor)?,
+            denominator,
+        })
+    }
+
+    /// Least common denominator of `self` and `other`.
+    pub fn common_denominator(&self, other: &Self) -> Option<i128> {
+        let divisor = gcd(self.denominator, other.denominator);
+        (self.denominator / divisor).checked_mul(other.denominator)
+    }
+
+    pub fn checked_neg(&self) -> Option<Self> {
+        Some(Self {
+            numerator: self.numerator.checked_neg()?,
+            denominator: self.denominator,
+        })
+    }
+
+    /// Adds fractions that already share a denominator, without reducing.
+    pub fn checked_add(&self, other: &Self) -> Option<Self> {
+        if self.denominator != other.denominator {
+            return None;
+        }
+        Some(Self {
+            numerator: self.numerator.checked_add(other.numerator)?,
+            denominator: self.denominator,
+        })
+    }
+
+    /// Subtracts fractions that already share a denominator, without
+    /// reducing.
+    pub fn checked_sub(&self, other: &Self) -> Option<Self> {
+        self.checked_add(&other.checked_neg()?)
+    }
+
+    pub fn checked_mul(&self, other: &Self) -> Option<Self> {
+        Self::new(
+            self.numerator.checked_mul(other.numerator)?,
+            self.denominator.checked_mul(other.denominator)?,
+        )
+    }
+
+    /// Returns `None` when dividing by zero or on overflow.
+    pub fn checked_div(&self, other: &Self) -> Option<Self> {
+        Self::new(
+            self.numerator.checked_mul(other.denominator)?,
+            self.denominator.checked_mul(other.numerator)?,
+        )
+    }
+
+    /// The largest whole number not above the fraction.
+    pub fn floor(&self) -> i128 {
+        self.numerator.div_euclid(self.denominator)
+    }
+
+    pub fn checked_pow(&self, exponent: i32) -> Option<Self> {
+        let power = exponent.unsigned_abs();
+        let result = Self::new(
+            self.numerator.checked_pow(power)?,
+            self.denominator.checked_pow(power)?,
+        )?;
+        if exponent < 0 {
+            Self::from_integer(1).checked_div(&result)
+        } else {
+            Some(result)
+        }
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_integer() {
+            write!(f, "{}", self.numerator)
+        } else {
+            write!(f, "{}/{}", self.numerator, self.denominator)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_f64() {
+        assert_eq!(Rational::from_f64(0.1), Rational::new(1, 10));
+        assert_eq!(Rational::from_f64(2.5), Rational::new(5, 2));
+        assert_eq!(Rational::from_f64(-3.0), Some(Rational::from_integer(-3)));
+        assert_eq!(Rational::from_f64(f64::NAN), None);
+    }
+
+    #[test]
+    fn test_approximate() {
+        assert_eq!(Rational::approximate(1.0 / 3.0, 1000), Rational::new(1, 3));
+        assert_eq!(Rational::approximate(-0.4, 1000), Rational::new(-2, 5));
+        assert_eq!(Rational::approximate(2.0, 1000), Rational::new(2, 1));
+        assert_eq!(Rational::approximate(2f64.sqrt(), 1000), None);
+    }
+
+    #[test]
+    fn test_arithmetic_keeps_unreduced_form() {
+        let third = Rational::new(1, 3).unwrap();
+        let sixth = Rational::new(1, 6).unwrap();
+        let denominator = third.common_denominator(&sixth).unwrap();
+        assert_eq!(denominator, 6);
+
+        let sum = third
+            .expanded_to(denominator)
+            .unwrap()
+            .checked_add(&sixth)
+            .unwrap();
+        assert_eq!(sum.to_string(), "3/6");
+        assert!(!sum.is_reduced());
+        assert_eq!(sum.reduced().to_string(), "1/2");
+    }
+
+    #[test]
+    fn test_division_and_powers() {
+        let two_thirds = Rational::new(2, 3).unwrap();
+        assert_eq!(
+            two_thirds.checked_div(&Rational::new(-4, 1).unwrap()),
+            Rational::new(-2, 12)
+        );
+        assert_eq!(two_thirds.checked_div(&Rational::from_integer(0)), None);
+        assert_eq!(two_thirds.checked_pow(-2), Rational::new(9, 4));
+        assert_eq!(Rational::new(7, 2).unwrap().floor(), 3);
+        assert_eq!(Rational::new(-7, 2).unwrap().floor(), -4);
+    }
+}
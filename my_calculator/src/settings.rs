@@ -0,0 +1,592 @@
+use std::f64::consts::PI;
+use std::fmt;
+
+/// How many digits of a number are shown in the evaluation steps.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Precision {
+    DecimalPlaces(usize),
+    SignificantDigits(usize),
+}
+
+impl Precision {
+    /// Rounds `value` to the digits that would be displayed.
+    pub fn round(self, value: f64) -> f64 {
+        if !value.is_finite() || value == 0.0 {
+            return value;
+        }
+        match self.format(value).parse::<f64>() {
+            Ok(rounded) => rounded,
+            Err(_) => value,
+        }
+    }
+
+    /// Formats `value` for display. Very large and very small magnitudes are
+    /// written in scientific notation, using the same `6.022e23` syntax the
+    /// lexer accepts.
+    pub fn format(self, value: f64) -> String {
+        let magnitude = value.abs();
+        if magnitude != 0.0 && magnitude.is_finite() && !(1e-6..1e15).contains(&magnitude) {
+            let mantissa_digits = match self {
+                Precision::DecimalPlaces(places) => places,
+                Precision::SignificantDigits(digits) => digits.saturating_sub(1),
+            };
+            return Self::trim_zeros(&format!("{:.*e}", mantissa_digits, value));
+        }
+        let (value, places) = match self {
+            Precision::DecimalPlaces(places) => (value, places),
+            Precision::SignificantDigits(digits) if magnitude != 0.0 => {
+                let exponent = magnitude.log10().floor() as i64;
+                let places = digits as i64 - 1 - exponent;
+                if places < 0 {
+                    // More integer digits than significant ones: round the
+                    // surplus to zeros, so 3141.59 with 3 digits is 3140.
+                    let scale = 10f64.powi(-places as i32);
+                    ((value / scale).round() * scale, 0)
+                } else {
+                    (value, places as usize)
+                }
+            }
+            Precision::SignificantDigits(_) => (value, 0),
+        };
+        let formatted = Self::trim_zeros(&format!("{:.*}", places, value));
+        if formatted == "-0" {
+            "0".to_string()
+        } else {
+            formatted
+        }
+    }
+
+    /// Drops trailing zeros after the decimal point, keeping any exponent.
+    fn trim_zeros(formatted: &str) -> String {
+        let (mantissa, exponent) = match formatted.find('e') {
+            Some(index) => formatted.split_at(index),
+            None => (formatted, ""),
+        };
+        let mantissa = if mantissa.contains('.') {
+            mantissa.trim_end_matches('0').trim_end_matches('.')
+        } else {
+            mantissa
+        };
+        format!("{}{}", mantissa, exponent)
+    }
+}
+
+impl fmt::Display for Precision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Precision::DecimalPlaces(places) => write!(f, "{} decimal places", places),
+            Precision::SignificantDigits(digits) => write!(f, "{} significant digits", digits),
+        }
+    }
+}
+
+/// Unit used for the arguments of trigonometric functions and the results
+/// of their inverses.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum AngleMode {
+    Degrees,
+    Radians,
+    Gradians,
+}
+
+impl AngleMode {
+    pub fn to_radians(self, angle: f64) -> f64 {
+        match self {
+            AngleMode::Degrees => angle.to_radians(),
+            AngleMode::Radians => angle,
+            AngleMode::Gradians => angle * PI / 200.0,
+        }
+    }
+
+    pub fn radians_to(self, radians: f64) -> f64 {
+        match self {
+            AngleMode::Degrees => radians.to_degrees(),
+            AngleMode::Radians => radians,
+            AngleMode::Gradians => radians * 200.0 / PI,
+        }
+    }
+
+    /// Converts `angle`, given in `self`, into `target` units.
+    pub fn convert(self, angle: f64, target: AngleMode) -> f64 {
+        if self == target {
+            angle
+        } else {
+            target.radians_to(self.to_radians(angle))
+        }
+    }
+
+    /// Suffix written after an angle in this unit, e.g. `30°` or `1.2 rad`.
+    pub fn suffix(self, output: Output) -> &'static str {
+        match (self, output) {
+            (AngleMode::Degrees, Output::Ascii) => " deg",
+            (AngleMode::Degrees, _) => "°",
+            (AngleMode::Radians, _) => " rad",
+            (AngleMode::Gradians, _) => " grad",
+        }
+    }
+}
+
+impl fmt::Display for AngleMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AngleMode::Degrees => write!(f, "degrees"),
+            AngleMode::Radians => write!(f, "radians"),
+            AngleMode::Gradians => write!(f, "gradians"),
+        }
+    }
+}
+
+/// Which spelling of the trigonometric functions the steps show, whatever
+/// spelling was typed.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FunctionNames {
+    /// `tg`, `cotg`, `atg`, `actg`
+    Continental,
+    /// `tan`, `cot`, `atan`, `acot`
+    Anglophone,
+}
+
+impl fmt::Display for FunctionNames {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FunctionNames::Continental => write!(f, "continental"),
+            FunctionNames::Anglophone => write!(f, "anglophone"),
+        }
+    }
+}
+
+/// Which characters the steps are written with.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Output {
+    /// `2 * pi`, `sqrt(x)`, `x ^ 2`, `30 deg`
+    Ascii,
+    /// `2 * π`, `sqrt(x)`, `x ^ 2`, `30°`, with `×` and `÷` in the
+    /// descriptions of the steps
+    Unicode,
+    /// `2 × π`, `√(x)`, `x²`, `30°`
+    Math,
+}
+
+impl fmt::Display for Output {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Output::Ascii => write!(f, "ascii"),
+            Output::Unicode => write!(f, "unicode"),
+            Output::Math => write!(f, "math"),
+        }
+    }
+}
+
+/// A fixed-width two's-complement integer type of the programmer mode,
+/// e.g. `i32` or `u8`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Word {
+    /// 8, 16, 32 or 64.
+    pub bits: u32,
+    pub signed: bool,
+}
+
+impl Word {
+    /// Reduces `value` modulo 2^bits into the range of the type, the way
+    /// the hardware wraps on overflow.
+    pub fn wrap(self, value: i128) -> i128 {
+        let mask = (1i128 << self.bits) - 1;
+        let low = value & mask;
+        if self.signed && low >> (self.bits - 1) == 1 {
+            low - (1i128 << self.bits)
+        } else {
+            low
+        }
+    }
+
+    /// Writes `value`, which must be in range, in `base`. Other bases than
+    /// decimal show the two's-complement bits, so -1 in `i8` is `0xff`.
+    pub fn format(self, value: i128, base: Base) -> String {
+        let bits = (value & ((1i128 << self.bits) - 1)) as u128;
+        match base {
+            Base::Decimal => value.to_string(),
+            Base::Hexadecimal => format!("0x{:x}", bits),
+            Base::Octal => format!("0o{:o}", bits),
+            Base::Binary => format!("0b{:b}", bits),
+        }
+    }
+
+    /// Whether `value` is in the range of the type, e.g. -128 to 127 for
+    /// `i8`.
+    pub fn contains(self, value: i128) -> bool {
+        if self.signed {
+            let half = 1i128 << (self.bits - 1);
+            (-half..half).contains(&value)
+        } else {
+            (0..1i128 << self.bits).contains(&value)
+        }
+    }
+
+    /// `value` in every base, e.g. `10 = 0xa = 0o12 = 0b1010`.
+    pub fn all_bases(self, value: i128) -> String {
+        [Base::Decimal, Base::Hexadecimal, Base::Octal, Base::Binary]
+            .map(|base| self.format(value, base))
+            .join(" = ")
+    }
+}
+
+impl fmt::Display for Word {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sign = if self.signed { 'i' } else { 'u' };
+        write!(f, "{}{}", sign, self.bits)
+    }
+}
+
+/// The base programmer mode writes whole numbers in.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Base {
+    Decimal,
+    Hexadecimal,
+    Octal,
+    Binary,
+}
+
+impl fmt::Display for Base {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Base::Decimal => write!(f, "dec"),
+            Base::Hexadecimal => write!(f, "hex"),
+            Base::Octal => write!(f, "oct"),
+            Base::Binary => write!(f, "bin"),
+        }
+    }
+}
+
+/// How many operations a single evaluation step applies.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Reduction {
+    /// Exactly one operation per step: the leftmost operation whose operands
+    /// are already numbers.
+    Strict,
+    /// Every operation whose operands are already numbers, all at once.
+    Level,
+}
+
+impl fmt::Display for Reduction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Reduction::Strict => write!(f, "strict"),
+            Reduction::Level => write!(f, "level"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Settings {
+    /// Display precision of the steps. Internal values keep full precision.
+    pub precision: Precision,
+    /// Rounds every intermediate result to the display precision, the way
+    /// a calculation done by hand would.
+    pub school_rounding: bool,
+    pub angle_mode: AngleMode,
+    /// Keeps `+ - * /` and integer powers of rationals as exact fractions.
+    pub exact: bool,
+    /// Largest number of digits an exact integer result may have.
+    pub max_digits: usize,
+    pub reduction: Reduction,
+    /// Evaluates `x!` for real `x` as `Γ(x + 1)` instead of rejecting it.
+    pub gamma_factorial: bool,
+    pub function_names: FunctionNames,
+    pub output: Output,
+    /// Integer type of the programmer mode, or `None` when it is off.
+    pub programmer: Option<Word>,
+    pub base: Base,
+    /// Relative tolerance of `==` and the other comparisons between floats:
+    /// a and b are equal when they differ by at most this times the larger
+    /// of |a|, |b| and 1.
+    pub tolerance: f64,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            precision: Precision::DecimalPlaces(2),
+            school_rounding: false,
+            angle_mode: AngleMode::Degrees,
+            exact: false,
+            max_digits: 1000,
+            reduction: Reduction::Strict,
+            gamma_factorial: false,
+            function_names: FunctionNames::Continental,
+            output: Output::Unicode,
+            programmer: None,
+            base: Base::Decimal,
+            tolerance: 1e-9,
+        }
+    }
+}
+
+impl Settings {
+    /// Applies a `set` command from the REPL, e.g. `precision 4`, `digits 6`
+    /// or `school on`, and returns a confirmation message.
+    pub fn apply(&mut self, command: &str) -> Result<String, String> {
+        let mut words = command.split_whitespace();
+        let (Some(name), Some(value), None) = (words.next(), words.next(), words.next()) else {
+            return Err(format!(
+                "Expected \"set <setting> <value>\", got \"set {}\"",
+                command
+            ));
+        };
+        match name.to_ascii_lowercase().as_str() {
+            "precision" => {
+                let places = Self::parse_count(value, 0, 15)?;
+                self.precision = Precision::DecimalPlaces(places);
+            }
+            "digits" => {
+                let digits = Self::parse_count(value, 1, 17)?;
+                self.precision = Precision::SignificantDigits(digits);
+            }
+            "school" => self.school_rounding = Self::parse_switch(value)?,
+            "exact" => self.exact = Self::parse_switch(value)?,
+            "gamma" => self.gamma_factorial = Self::parse_switch(value)?,
+            "maxdigits" => self.max_digits = Self::parse_count(value, 1, 1_000_000)?,
+            "tolerance" => {
+                self.tolerance = match value.parse::<f64>() {
+                    Ok(tolerance) if (0.0..1.0).contains(&tolerance) => tolerance,
+                    _ => {
+                        return Err(format!(
+                            "Expected a tolerance of at least 0 and below 1, got \"{}\"",
+                            value
+                        ))
+                    }
+                }
+            }
+            "steps" => {
+                self.reduction = match value.to_ascii_lowercase().as_str() {
+                    "strict" => Reduction::Strict,
+                    "level" => Reduction::Level,
+                    _ => {
+                        return Err(format!(
+                            "Expected \"strict\" or \"level\", got \"{}\"",
+                            value
+                        ))
+                    }
+                }
+            }
+            "names" => {
+                self.function_names = match value.to_ascii_lowercase().as_str() {
+                    "continental" => FunctionNames::Continental,
+                    "anglophone" => FunctionNames::Anglophone,
+                    _ => {
+                        return Err(format!(
+                            "Expected \"continental\" or \"anglophone\", got \"{}\"",
+                            value
+                        ))
+                    }
+                }
+            }
+            "output" => {
+                self.output = match value.to_ascii_lowercase().as_str() {
+                    "ascii" => Output::Ascii,
+                    "unicode" => Output::Unicode,
+                    "math" => Output::Math,
+                    _ => {
+                        return Err(format!(
+                            "Expected \"ascii\", \"unicode\" or \"math\", got \"{}\"",
+                            value
+                        ))
+                    }
+                }
+            }
+            "programmer" => {
+                self.programmer = match value.to_ascii_lowercase().as_str() {
+                    "off" => None,
+                    name => Some(Self::parse_word(name).ok_or_else(|| {
+                        format!(
+                            "Expected \"off\" or a type such as \"i32\" or \"u8\", got \"{}\"",
+                            value
+                        )
+                    })?),
+                }
+            }
+            "base" => {
+                self.base = match value.to_ascii_lowercase().as_str() {
+                    "dec" => Base::Decimal,
+                    "hex" => Base::Hexadecimal,
+                    "oct" => Base::Octal,
+                    "bin" => Base::Binary,
+                    _ => {
+                        return Err(format!(
+                            "Expected \"dec\", \"hex\", \"oct\" or \"bin\", got \"{}\"",
+                            value
+                        ))
+                    }
+                }
+            }
+            "angle" => {
+                self.angle_mode = match value.to_ascii_lowercase().as_str() {
+                    "deg" | "degrees" => AngleMode::Degrees,
+                    "rad" | "radians" => AngleMode::Radians,
+                    "grad" | "gradians" => AngleMode::Gradians,
+                    _ => {
+                        return Err(format!(
+                            "Expected \"deg\", \"rad\" or \"grad\", got \"{}\"",
+                            value
+                        ))
+                    }
+                }
+            }
+            _ => return Err(format!("Unknown setting: {}", name)),
+        }
+        Ok(format!("Settings: {}", self))
+    }
+
+    fn parse_count(value: &str, min: usize, max: usize) -> Result<usize, String> {
+        match value.parse::<usize>() {
+            Ok(count) if (min..=max).contains(&count) => Ok(count),
+            _ => Err(format!(
+                "Expected a whole number between {} and {}, got \"{}\"",
+                min, max, value
+            )),
+        }
+    }
+
+    /// Reads `i8`, `i16`, `i32`, `i64` or their unsigned `u` counterparts.
+    fn parse_word(name: &str) -> Option<Word> {
+        let signed = match name.chars().next()? {
+            'i' => true,
+            'u' => false,
+            _ => return None,
+        };
+        match name[1..].parse::<u32>().ok()? {
+            bits @ (8 | 16 | 32 | 64) => Some(Word { bits, signed }),
+            _ => None,
+        }
+    }
+
+    fn switch_name(value: bool) -> &'static str {
+        if value {
+            "on"
+        } else {
+            "off"
+        }
+    }
+
+    fn parse_switch(value: &str) -> Result<bool, String> {
+        match value.to_ascii_lowercase().as_str() {
+            "on" => Ok(true),
+            "off" => Ok(false),
+            _ => Err(format!("Expected \"on\" or \"off\", got \"{}\"", value)),
+        }
+    }
+}
+
+impl fmt::Display for Settings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "precision = {}, school rounding = {}, angles in {}, exact fractions = {}, \
+             max digits = {}, steps = {}, real factorials = {}, function names = {}, output = {}, \
+             programmer = {}, tolerance = {:e}",
+            self.precision,
+            Self::switch_name(self.school_rounding),
+            self.angle_mode,
+            Self::switch_name(self.exact),
+            self.max_digits,
+            self.reduction,
+            Self::switch_name(self.gamma_factorial),
+            self.function_names,
+            self.output,
+            match self.programmer {
+                Some(word) => format!("{} in {}", word, self.base),
+                None => "off".to_string(),
+            },
+            self.tolerance
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decimal_places() {
+        let precision = Precision::DecimalPlaces(2);
+        assert_eq!(precision.format(3141.592653), "3141.59");
+        assert_eq!(precision.format(2.5), "2.5");
+        assert_eq!(precision.format(-0.001), "0");
+        assert_eq!(precision.round(1.23456), 1.23);
+    }
+
+    #[test]
+    fn test_significant_digits() {
+        let precision = Precision::SignificantDigits(3);
+        assert_eq!(precision.format(3141.592653), "3140");
+        assert_eq!(precision.format(0.000123456), "0.000123");
+        assert_eq!(precision.round(1.98765), 1.99);
+        assert_eq!(precision.round(-98765.4), -98800.0);
+    }
+
+    #[test]
+    fn test_scientific_notation_output() {
+        let precision = Precision::DecimalPlaces(2);
+        assert_eq!(precision.format(6.022e23), "6.02e23");
+        assert_eq!(precision.format(-1.5e-7), "-1.5e-7");
+        assert_eq!(precision.format(123456.5), "123456.5");
+        assert_eq!(precision.format(0.0), "0");
+        assert_eq!(Precision::SignificantDigits(4).format(6.022e23), "6.022e23");
+    }
+
+    #[test]
+    fn test_apply_commands() {
+        let mut settings = Settings::default();
+        settings.apply("digits 6").unwrap();
+        assert_eq!(settings.precision, Precision::SignificantDigits(6));
+        settings.apply("school on").unwrap();
+        assert!(settings.school_rounding);
+        settings.apply("angle rad").unwrap();
+        assert_eq!(settings.angle_mode, AngleMode::Radians);
+        settings.apply("steps level").unwrap();
+        assert_eq!(settings.reduction, Reduction::Level);
+        settings.apply("names anglophone").unwrap();
+        assert_eq!(settings.function_names, FunctionNames::Anglophone);
+        settings.apply("output math").unwrap();
+        assert_eq!(settings.output, Output::Math);
+        settings.apply("programmer u16").unwrap();
+        assert_eq!(
+            settings.programmer,
+            Some(Word {
+                bits: 16,
+                signed: false
+            })
+        );
+        settings.apply("tolerance 1e-6").unwrap();
+        assert_eq!(settings.tolerance, 1e-6);
+        assert!(settings.apply("tolerance -1").is_err());
+        assert!(settings.apply("programmer i12").is_err());
+        assert!(settings.apply("precision many").is_err());
+        assert!(settings.apply("colour blue").is_err());
+    }
+
+    #[test]
+    fn test_words() {
+        let i8 = Word {
+            bits: 8,
+            signed: true,
+        };
+        assert_eq!(i8.wrap(255), -1);
+        assert_eq!(i8.wrap(128), -128);
+        assert_eq!(i8.wrap(-129), 127);
+        assert_eq!(i8.format(-1, Base::Hexadecimal), "0xff");
+        assert!(i8.contains(-128) && !i8.contains(128));
+        let u64 = Word {
+            bits: 64,
+            signed: false,
+        };
+        assert_eq!(u64.wrap(-1), u64::MAX as i128);
+        assert_eq!(u64.all_bases(10), "10 = 0xa = 0o12 = 0b1010");
+    }
+
+    #[test]
+    fn test_angle_conversion() {
+        assert!((AngleMode::Degrees.convert(180.0, AngleMode::Radians) - PI).abs() < 1e-12);
+        assert!((AngleMode::Gradians.convert(100.0, AngleMode::Degrees) - 90.0).abs() < 1e-12);
+        assert_eq!(AngleMode::Radians.convert(1.2, AngleMode::Radians), 1.2);
+    }
+}
@@ -0,0 +1,113 @@
+use crate::step::Trace;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Somewhere a finished evaluation can be shown or stored.
+pub trait TraceSink {
+    fn write_trace(&mut self, trace: &Trace) -> io::Result<()>;
+}
+
+/// Prints the evaluation as `= ...` lines, the way the calculator shows it.
+pub struct StdoutSink;
+
+impl TraceSink for StdoutSink {
+    fn write_trace(&mut self, trace: &Trace) -> io::Result<()> {
+        let mut stdout = io::stdout().lock();
+        for line in trace.lines() {
+            writeln!(stdout, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// Saves the evaluation with every step annotated.
+pub struct FileSink {
+    file: File,
+}
+
+impl FileSink {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            file: File::create(path)?,
+        })
+    }
+}
+
+impl TraceSink for FileSink {
+    fn write_trace(&mut self, trace: &Trace) -> io::Result<()> {
+        write_annotated(&mut self.file, trace)
+    }
+}
+
+/// Collects the traces, for callers that render them themselves.
+#[derive(Debug, Default)]
+pub struct MemorySink {
+    pub traces: Vec<Trace>,
+}
+
+impl TraceSink for MemorySink {
+    fn write_trace(&mut self, trace: &Trace) -> io::Result<()> {
+        self.traces.push(trace.clone());
+        Ok(())
+    }
+}
+
+/// Writes the expression followed by one line per step, e.g.
+/// `Step 2: multiplication → 2 + 48`, each with the operations it applied
+/// and its warnings indented below it.
+fn write_annotated(out: &mut impl Write, trace: &Trace) -> io::Result<()> {
+    writeln!(out, "= {}", trace.expression)?;
+    for (index, step) in trace.steps.iter().enumerate() {
+        writeln!(out, "Step {}: {}", index + 1, step)?;
+        for operation in &step.operations {
+            writeln!(out, "    {}", operation.description)?;
+        }
+        for warning in &step.warnings {
+            writeln!(out, "    warning: {}", warning)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::ASTNode;
+    use crate::step::{Operation, Rule, Step};
+
+    fn trace() -> Trace {
+        Trace {
+            expression: "2 * 3".to_string(),
+            steps: vec![Step {
+                before: "2 * 3".to_string(),
+                after: "6".to_string(),
+                operations: vec![Operation {
+                    rule: Rule::Multiplication,
+                    description: "multiply 2 × 3".to_string(),
+                    path: Vec::new(),
+                }],
+                warnings: Vec::new(),
+            }],
+            result: 6.0,
+            value: ASTNode::Number(6.0),
+        }
+    }
+
+    #[test]
+    fn test_annotated_output() {
+        let mut out = Vec::new();
+        write_annotated(&mut out, &trace()).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "= 2 * 3\nStep 1: multiplication → 6\n    multiply 2 × 3\n"
+        );
+    }
+
+    #[test]
+    fn test_memory_sink_collects_traces() {
+        let mut sink = MemorySink::default();
+        sink.write_trace(&trace()).unwrap();
+        assert_eq!(sink.traces, vec![trace()]);
+    }
+}
@@ -0,0 +1,147 @@
+use crate::parser::ASTNode;
+use std::fmt;
+
+/// The kind of rewrite an operation applied.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Rule {
+    Addition,
+    Subtraction,
+    Multiplication,
+    Division,
+    IntegerDivision,
+    Modulo,
+    Percent,
+    Power,
+    RealRoot,
+    Negation,
+    /// `&`, `|`, `xor` and `~` of the programmer mode.
+    Bitwise,
+    Shift,
+    /// `==`, `!=`, `<`, `<=`, `>` and `>=`.
+    Comparison,
+    /// `and`, `or` and `not`.
+    Logic,
+    Factorial,
+    DoubleFactorial,
+    Subfactorial,
+    Function,
+    Logarithm,
+    AngleConversion,
+    Constant,
+    Substitution,
+    CommonDenominator,
+    Simplification,
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Rule::Addition => "addition",
+            Rule::Subtraction => "subtraction",
+            Rule::Multiplication => "multiplication",
+            Rule::Division => "division",
+            Rule::IntegerDivision => "integer division",
+            Rule::Modulo => "modulo",
+            Rule::Percent => "percent",
+            Rule::Power => "power",
+            Rule::RealRoot => "real root",
+            Rule::Negation => "negation",
+            Rule::Bitwise => "bitwise operation",
+            Rule::Shift => "shift",
+            Rule::Comparison => "comparison",
+            Rule::Logic => "logic",
+            Rule::Factorial => "factorial",
+            Rule::DoubleFactorial => "double factorial",
+            Rule::Subfactorial => "subfactorial",
+            Rule::Function => "function",
+            Rule::Logarithm => "logarithm",
+            Rule::AngleConversion => "angle conversion",
+            Rule::Constant => "constant",
+            Rule::Substitution => "substitution",
+            Rule::CommonDenominator => "common denominator",
+            Rule::Simplification => "simplification",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A single rewrite of one subexpression.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Operation {
+    pub rule: Rule,
+    /// What was done, e.g. `multiply 3 × 16` or `evaluate sin(30°)`.
+    pub description: String,
+    /// Child indices leading from the root to the rewritten node: `0` and
+    /// `1` are the left and right operands of a binary operator, `0` the
+    /// only operand of a unary operator, angle or grouping, and the index of
+    /// an argument picks it out of a call. The root itself is `[]`.
+    pub path: Vec<usize>,
+}
+
+/// One step of an evaluation, from one printed expression to the next.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Step {
+    pub before: String,
+    pub after: String,
+    /// A single operation in strict mode, possibly several in level mode.
+    pub operations: Vec<Operation>,
+    /// Remarks about the step, such as a result rounded in school mode.
+    pub warnings: Vec<String>,
+}
+
+impl fmt::Display for Step {
+    /// Writes the step as `multiplication → 2 + 48`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rules: Vec<String> = self
+            .operations
+            .iter()
+            .map(|operation| operation.rule.to_string())
+            .collect();
+        write!(f, "{} → {}", rules.join(", "), self.after)
+    }
+}
+
+/// A complete evaluation: the expression as first shown, with implicit
+/// multiplications written out, every step and the final value.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Trace {
+    pub expression: String,
+    pub steps: Vec<Step>,
+    /// The final value; a comparison or other boolean result is 1 for true
+    /// and 0 for false.
+    pub result: f64,
+    /// The final node, which keeps exact fractions and large integers for
+    /// `ans` and assigned variables.
+    pub value: ASTNode,
+}
+
+impl Trace {
+    /// The evaluation as `= ...` lines, the expression followed by the
+    /// result of every step.
+    pub fn lines(&self) -> Vec<String> {
+        std::iter::once(&self.expression)
+            .chain(self.steps.iter().map(|step| &step.after))
+            .map(|expression| format!("= {}", expression))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_step_display() {
+        let step = Step {
+            before: "2 + 3 * 16".to_string(),
+            after: "2 + 48".to_string(),
+            operations: vec![Operation {
+                rule: Rule::Multiplication,
+                description: "multiply 3 × 16".to_string(),
+                path: vec![1],
+            }],
+            warnings: Vec::new(),
+        };
+        assert_eq!(step.to_string(), "multiplication → 2 + 48");
+    }
+}
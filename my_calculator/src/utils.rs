@@ -1,15 +1,12 @@
-use std::fs::File;
+use crate::sink::{FileSink, TraceSink};
+use crate::step::Trace;
 use std::fs::{read_dir, remove_file};
-use std::io::Write;
 
-pub fn save_to_file(file_name: &str, steps: &[String]) -> Result<(), std::io::Error> {
+/// Saves an annotated evaluation to `evaluations/<file_name>`.
+pub fn save_to_file(file_name: &str, trace: &Trace) -> Result<(), std::io::Error> {
     let mut path = String::from("evaluations/");
     path.push_str(file_name);
-    let mut file = File::create(path)?;
-    for step in steps {
-        writeln!(file, "{}", step)?;
-    }
-    Ok(())
+    FileSink::create(path)?.write_trace(trace)
 }
 
 pub fn delete_saved_evaluations() -> Result<(), std::io::Error> {